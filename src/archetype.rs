@@ -0,0 +1,268 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use crate::cell::SharedCell;
+use crate::storage::{ComponentPtr, ComponentPtrs, IterationOrder};
+use crate::{ComponentId, ComponentTicks, ComponentTrait, Entity, Tick};
+
+pub type ArchetypeId = usize;
+
+/// Type-erased column of an archetype table.
+pub(crate) trait Column: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn new_empty(&self) -> Box<dyn Column>;
+    fn remove(&mut self, row: usize, order: IterationOrder);
+    /// Removes the row and pushes it onto `to`, a column of the same type.
+    fn move_row(&mut self, row: usize, to: &mut dyn Column, order: IterationOrder);
+}
+
+pub struct TypedColumn<T> {
+    data: Vec<T>,
+    ticks: Vec<ComponentTicks>,
+    /// Taken again whenever `data` or `ticks` may have moved, for views to read without borrowing the column.
+    ptrs: ComponentPtrs<T>,
+}
+
+impl<T: ComponentTrait> TypedColumn<T> {
+    fn new() -> Self {
+        let (mut data, mut ticks) = (Vec::new(), Vec::new());
+        let ptrs = ComponentPtrs::new(&mut data, &mut ticks);
+        Self { data, ticks, ptrs }
+    }
+
+    fn push(&mut self, value: T, ticks: ComponentTicks) {
+        self.data.push(value);
+        self.ticks.push(ticks);
+        self.ptrs = ComponentPtrs::new(&mut self.data, &mut self.ticks);
+    }
+}
+
+impl<T: ComponentTrait> Column for TypedColumn<T> {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+    fn new_empty(&self) -> Box<dyn Column> { Box::new(Self::new()) }
+
+    fn remove(&mut self, row: usize, order: IterationOrder) {
+        order.remove(&mut self.data, row);
+        order.remove(&mut self.ticks, row);
+    }
+
+    fn move_row(&mut self, row: usize, to: &mut dyn Column, order: IterationOrder) {
+        let to = to.as_any_mut().downcast_mut::<Self>().unwrap();
+        to.push(order.remove(&mut self.data, row), order.remove(&mut self.ticks, row));
+    }
+}
+
+/// Owns a column, see [`SharedCell`].
+type ColumnCell = SharedCell<dyn Column>;
+
+fn new_column<T: ComponentTrait>() -> Box<dyn Column> {
+    Box::new(TypedColumn::<T>::new())
+}
+
+/// Cached transitions of the archetype graph: where an entity goes when the component is added or removed.
+#[derive(Debug, Default, Clone, Copy)]
+struct Edge {
+    add: Option<ArchetypeId>,
+    remove: Option<ArchetypeId>,
+}
+
+/// Table of the entities having exactly the same table-stored components, with one column per component type
+/// and one row per entity.
+pub struct Archetype {
+    components: Vec<ComponentId>,
+    columns: Vec<ColumnCell>,
+    entities: Vec<Entity>,
+    edges: HashMap<ComponentId, Edge>,
+}
+
+impl Archetype {
+    /// The table-stored component types of the archetype, sorted.
+    pub fn components(&self) -> &[ComponentId] { &self.components }
+    pub fn entities(&self) -> &[Entity] { &self.entities }
+    pub fn len(&self) -> usize { self.entities.len() }
+    pub fn is_empty(&self) -> bool { self.entities.is_empty() }
+
+    pub fn contains(&self, component_id: ComponentId) -> bool {
+        self.components.binary_search(&component_id).is_ok()
+    }
+
+    fn column_index(&self, component_id: ComponentId) -> Option<usize> {
+        self.components.binary_search(&component_id).ok()
+    }
+
+    fn column<T: ComponentTrait>(&self) -> Option<&TypedColumn<T>> {
+        let index = self.column_index(TypeId::of::<T>())?;
+        self.columns[index].get().as_any().downcast_ref()
+    }
+
+    fn column_mut<T: ComponentTrait>(&mut self) -> Option<&mut TypedColumn<T>> {
+        let index = self.column_index(TypeId::of::<T>())?;
+        self.columns[index].get_mut().as_any_mut().downcast_mut()
+    }
+
+    /// Pointers to the components of the column of `T`, if the archetype has one, read without borrowing the column.
+    pub(crate) fn column_ptrs<T: ComponentTrait>(&self) -> Option<ComponentPtrs<T>> {
+        let column = self.columns[self.column_index(TypeId::of::<T>())?].as_ptr().cast::<TypedColumn<T>>();
+        // SAFETY: the column of a component is always a typed column of it, and only views read it while shared
+        Some(unsafe { (*column.as_ptr()).ptrs })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Location {
+    pub archetype: ArchetypeId,
+    pub row: usize,
+}
+
+/// The archetype tables, the first of which has no components and holds no entities.
+pub struct Archetypes {
+    archetypes: Vec<Archetype>,
+    index: HashMap<Vec<ComponentId>, ArchetypeId>,
+    by_component: HashMap<ComponentId, Vec<ArchetypeId>>,
+    locations: HashMap<Entity, Location>,
+}
+
+impl Default for Archetypes {
+    fn default() -> Self {
+        let root = Archetype { components: Vec::new(), columns: Vec::new(), entities: Vec::new(), edges: HashMap::new() };
+        Self { archetypes: vec![root], index: HashMap::from([(Vec::new(), 0)]), by_component: HashMap::new(), locations: HashMap::new() }
+    }
+}
+
+impl Archetypes {
+    pub(crate) fn iter(&self) -> std::slice::Iter<'_, Archetype> { self.archetypes.iter() }
+    pub(crate) fn get(&self, archetype: ArchetypeId) -> &Archetype { &self.archetypes[archetype] }
+    pub(crate) fn location(&self, entity: Entity) -> Option<Location> { self.locations.get(&entity).copied() }
+
+    /// The archetypes having the component, in creation order.
+    pub(crate) fn with_component(&self, component_id: ComponentId) -> &[ArchetypeId] {
+        self.by_component.get(&component_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Some entity having the component, if any.
+    pub(crate) fn any_with(&self, component_id: ComponentId) -> Option<Entity> {
+        self.with_component(component_id).iter().find_map(|archetype| self.archetypes[*archetype].entities.last().copied())
+    }
+
+    /// Pointers to the component of the entity, if it has one in a table.
+    pub(crate) fn component_ptr<T: ComponentTrait>(&self, entity: Entity) -> Option<ComponentPtr<T>> {
+        let location = self.location(entity)?;
+        let column = self.archetypes[location.archetype].column_ptrs::<T>()?;
+        // SAFETY: the entity is at `row` in the column
+        Some(unsafe { column.at(location.row) })
+    }
+
+    pub(crate) fn component<T: ComponentTrait>(&self, entity: Entity) -> Option<&T> {
+        let location = self.location(entity)?;
+        self.archetypes[location.archetype].column::<T>().map(|column| &column.data[location.row])
+    }
+
+    pub(crate) fn ticks<T: ComponentTrait>(&self, entity: Entity) -> Option<ComponentTicks> {
+        let location = self.location(entity)?;
+        self.archetypes[location.archetype].column::<T>().map(|column| column.ticks[location.row])
+    }
+
+    pub(crate) fn get_mut_changed<T: ComponentTrait>(&mut self, entity: Entity, tick: Tick) -> Option<&mut T> {
+        let location = self.location(entity)?;
+        let column = self.archetypes[location.archetype].column_mut::<T>()?;
+        column.ticks[location.row].changed = tick;
+        Some(&mut column.data[location.row])
+    }
+
+    /// Inserts the component, moving the entity to the archetype having it, or returns the previous value.
+    pub(crate) fn insert<T: ComponentTrait>(&mut self, entity: Entity, value: T, tick: Tick, order: IterationOrder) -> Option<T> {
+        let source = self.location(entity);
+        if let Some(location) = source {
+            if let Some(column) = self.archetypes[location.archetype].column_mut::<T>() {
+                column.ticks[location.row].changed = tick;
+                return Some(std::mem::replace(&mut column.data[location.row], value));
+            }
+        }
+        let target = self.transition(source.map_or(0, |location| location.archetype), TypeId::of::<T>(), Some(new_column::<T>));
+        self.move_entity(entity, source, target, order);
+        self.archetypes[target].column_mut::<T>().unwrap().push(value, ComponentTicks::new(tick));
+        None
+    }
+
+    /// Drops the component, moving the entity to the archetype without it.
+    pub(crate) fn remove(&mut self, entity: Entity, component_id: ComponentId, order: IterationOrder) -> bool {
+        let Some(location) = self.location(entity) else { return false };
+        if !self.archetypes[location.archetype].contains(component_id) {
+            return false;
+        }
+        let target = self.transition(location.archetype, component_id, None);
+        self.move_entity(entity, Some(location), target, order);
+        true
+    }
+
+    /// Follows the edge of the archetype graph for adding the component, given how to create its column,
+    /// or for removing it; the target archetype is created on first use.
+    fn transition(&mut self, source: ArchetypeId, component_id: ComponentId, new_column: Option<fn() -> Box<dyn Column>>) -> ArchetypeId {
+        let add = new_column.is_some();
+        let edge = self.archetypes[source].edges.get(&component_id).copied().unwrap_or_default();
+        if let Some(target) = if add { edge.add } else { edge.remove } {
+            return target;
+        }
+        let mut components = self.archetypes[source].components.clone();
+        match components.binary_search(&component_id) {
+            Err(position) if add => components.insert(position, component_id),
+            Ok(position) if !add => { components.remove(position); }
+            _ => unreachable!(),
+        }
+        let target = match self.index.get(&components) {
+            Some(target) => *target,
+            None => {
+                let source = &self.archetypes[source];
+                let columns = components.iter().map(|id| match source.column_index(*id) {
+                    Some(index) => SharedCell::new(source.columns[index].get().new_empty()),
+                    None => SharedCell::new(new_column.unwrap()()),
+                }).collect();
+                let target = self.archetypes.len();
+                for id in &components {
+                    self.by_component.entry(*id).or_default().push(target);
+                }
+                self.index.insert(components.clone(), target);
+                self.archetypes.push(Archetype { components, columns, entities: Vec::new(), edges: HashMap::new() });
+                target
+            }
+        };
+        let forward = self.archetypes[source].edges.entry(component_id).or_default();
+        if add { forward.add = Some(target) } else { forward.remove = Some(target) }
+        let backward = self.archetypes[target].edges.entry(component_id).or_default();
+        if add { backward.remove = Some(source) } else { backward.add = Some(source) }
+        target
+    }
+
+    /// Moves the entity and its components to the end of the target table, dropping the ones the target lacks.
+    fn move_entity(&mut self, entity: Entity, source: Option<Location>, target: ArchetypeId, order: IterationOrder) {
+        if let Some(Location { archetype, row }) = source {
+            let (from, to) = if archetype < target {
+                let (left, right) = self.archetypes.split_at_mut(target);
+                (&mut left[archetype], &mut right[0])
+            } else {
+                let (left, right) = self.archetypes.split_at_mut(archetype);
+                (&mut right[0], &mut left[target])
+            };
+            for (index, component_id) in from.components.iter().enumerate() {
+                let column = from.columns[index].get_mut();
+                match to.column_index(*component_id) {
+                    Some(to_index) => column.move_row(row, to.columns[to_index].get_mut(), order),
+                    None => column.remove(row, order),
+                }
+            }
+            order.remove(&mut from.entities, row);
+            for row in order.moved(row, from.entities.len()) {
+                self.locations.insert(from.entities[row], Location { archetype, row });
+            }
+        }
+        if target == 0 {
+            self.locations.remove(&entity);
+        } else {
+            let row = self.archetypes[target].entities.len();
+            self.archetypes[target].entities.push(entity);
+            self.locations.insert(entity, Location { archetype: target, row });
+        }
+    }
+}
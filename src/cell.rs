@@ -0,0 +1,38 @@
+use std::cell::UnsafeCell;
+use std::ptr::NonNull;
+
+/// Owns a boxed value that views and systems borrow through raw pointers while the registry is only shared, so that
+/// several of them can read it, or one can write it, at the same time.
+pub(crate) struct SharedCell<T: ?Sized>(UnsafeCell<Box<T>>);
+
+// SAFETY: through a shared cell, the value is only referenced by `get`, which hands out `&T`, and `as_ptr` creates no
+// reference at all. Writing through its pointer is up to callers who guarantee that nothing else, on any thread,
+// borrows what they write, so sharing the cell is as safe as sharing `T`.
+unsafe impl<T: ?Sized + Sync> Sync for SharedCell<T> {}
+
+impl<T: ?Sized> SharedCell<T> {
+    pub fn new(value: Box<T>) -> Self {
+        Self(UnsafeCell::new(value))
+    }
+
+    pub fn get(&self) -> &T {
+        // SAFETY: see the `Sync` impl
+        unsafe { &*self.0.get() }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.0.get_mut()
+    }
+
+    pub fn into_inner(self) -> Box<T> {
+        self.0.into_inner()
+    }
+
+    /// A pointer to the value, valid as long as the cell is. Reading through it requires that nothing writes the
+    /// value meanwhile, writing that nothing else borrows what is written.
+    pub fn as_ptr(&self) -> NonNull<T> {
+        // SAFETY: a box is never null; no reference is created on the way, so pointers handed out for the same value
+        // never invalidate each other
+        unsafe { NonNull::new_unchecked(&raw mut **self.0.get()) }
+    }
+}
@@ -0,0 +1,98 @@
+use std::iter::FromIterator;
+
+use crate::{ComponentTrait, ComponentTuple, Entity, Registry};
+
+type Command = Box<dyn FnOnce(&mut Registry) + Send>;
+
+/// Structural changes recorded while the registry is borrowed, applied later by [`Registry::apply`].
+///
+/// Commands on entities that no longer exist by the time they are applied do nothing.
+#[derive(Default)]
+pub struct Commands {
+    commands: Vec<Command>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize { self.commands.len() }
+    pub fn is_empty(&self) -> bool { self.commands.is_empty() }
+
+    /// Records an arbitrary change.
+    pub fn push<F: FnOnce(&mut Registry) + Send + 'static>(&mut self, command: F) -> &mut Self {
+        self.commands.push(Box::new(command));
+        self
+    }
+
+    pub fn create_with<Components: ComponentTuple<'static> + Send + 'static>(&mut self, components: Components) -> &mut Self {
+        self.push(move |registry| {
+            components.create_entity_with(registry);
+        })
+    }
+
+    /// Creates an entity reserved with [`Registry::reserve`]; panics when applied if it was not reserved.
+    pub fn create_reserved(&mut self, entity: Entity) -> &mut Self {
+        self.push(move |registry| {
+            if let Err(error) = registry.create_reserved(entity) {
+                panic!("create_reserved: {}", error);
+            }
+        })
+    }
+
+    pub fn create_reserved_with<Components: ComponentTuple<'static> + Send + 'static>(&mut self, entity: Entity, components: Components) -> &mut Self {
+        self.create_reserved(entity).push(move |registry| components.add_components(entity, registry))
+    }
+
+    pub fn destroy(&mut self, entity: Entity) -> &mut Self {
+        self.push(move |registry| registry.destroy(entity))
+    }
+
+    pub fn destroy_recursive(&mut self, entity: Entity) -> &mut Self {
+        self.push(move |registry| registry.destroy_recursive(entity))
+    }
+
+    /// Panics when applied if `parent` is `child` or one of its descendants, see [`Registry::set_parent`].
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) -> &mut Self {
+        self.push(move |registry| {
+            if registry.exists(child) && registry.exists(parent) {
+                registry.set_parent(child, parent);
+            }
+        })
+    }
+
+    pub fn add<Component: ComponentTrait>(&mut self, entity: Entity, component: Component) -> &mut Self {
+        self.push(move |registry| registry.add(entity, component))
+    }
+
+    pub fn emplace_or_replace<Component: ComponentTrait>(&mut self, entity: Entity, component: Component) -> &mut Self {
+        self.push(move |registry| registry.emplace_or_replace(entity, component))
+    }
+
+    pub fn remove<Component: ComponentTrait>(&mut self, entity: Entity) -> &mut Self {
+        self.push(move |registry| registry.remove::<Component>(entity))
+    }
+
+    /// Moves the commands of `other` after the ones of `self`, leaving `other` empty.
+    pub fn append(&mut self, other: &mut Commands) {
+        self.commands.append(&mut other.commands);
+    }
+
+    pub(crate) fn apply(self, registry: &mut Registry) {
+        for command in self.commands {
+            command(registry);
+        }
+    }
+}
+
+/// Merges buffers in iteration order, however they were filled.
+impl FromIterator<Commands> for Commands {
+    fn from_iter<I: IntoIterator<Item = Commands>>(buffers: I) -> Self {
+        let mut merged = Commands::new();
+        for mut commands in buffers {
+            merged.append(&mut commands);
+        }
+        merged
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    pub fn index(&self) -> u32 { self.index }
+    pub fn generation(&self) -> u32 { self.generation }
+}
+
+/// Hands out entity ids, recycling freed indices with a bumped generation.
+///
+/// `reserve` works through a shared reference: it claims entries of `free` by moving `free_cursor` down, and once
+/// they run out, fresh indices past the end of `generations`. `flush` settles those claims before any mutation.
+#[derive(Debug, Default)]
+pub(crate) struct EntityAllocator {
+    generations: Vec<u32>,
+    /// Whether each index was freed and not handed out again since, as listing `free` would take a scan.
+    freed: Vec<bool>,
+    free: Vec<u32>,
+    /// Length of the unclaimed part of `free`; when negative, how many fresh indices were reserved.
+    free_cursor: AtomicI64,
+}
+
+impl EntityAllocator {
+    pub fn alloc(&mut self) -> Entity {
+        self.flush();
+        let entity = match self.free.pop() {
+            Some(index) => {
+                self.freed[index as usize] = false;
+                Entity { index, generation: self.generations[index as usize] }
+            }
+            None => {
+                let index = self.generations.len() as u32;
+                self.generations.push(0);
+                self.freed.push(false);
+                Entity { index, generation: 0 }
+            }
+        };
+        *self.free_cursor.get_mut() = self.free.len() as i64;
+        entity
+    }
+
+    pub fn reserve(&self) -> Entity {
+        let cursor = self.free_cursor.fetch_sub(1, Ordering::Relaxed);
+        if cursor > 0 {
+            let index = self.free[cursor as usize - 1];
+            Entity { index, generation: self.generations[index as usize] }
+        } else {
+            Entity { index: (self.generations.len() as i64 - cursor) as u32, generation: 0 }
+        }
+    }
+
+    /// Makes the reserved ids allocated ones.
+    pub fn flush(&mut self) {
+        let cursor = *self.free_cursor.get_mut();
+        for index in self.free.drain(cursor.max(0) as usize..) {
+            self.freed[index as usize] = false;
+        }
+        if cursor < 0 {
+            self.generations.resize(self.generations.len() + cursor.unsigned_abs() as usize, 0);
+            self.freed.resize(self.generations.len(), false);
+        }
+        *self.free_cursor.get_mut() = self.free.len() as i64;
+    }
+
+    /// Whether the entity was handed out by `alloc` and not freed since; reserved ids must be flushed first.
+    pub fn is_allocated(&self, entity: Entity) -> bool {
+        self.generations.get(entity.index as usize) == Some(&entity.generation) && !self.freed[entity.index as usize]
+    }
+
+    pub fn free(&mut self, entity: Entity) {
+        self.flush();
+        self.freed[entity.index as usize] = true;
+        let generation = &mut self.generations[entity.index as usize];
+        // A slot whose generation would wrap is retired instead of recycled, so stale handles never come back to life
+        if let Some(next) = generation.checked_add(1) {
+            *generation = next;
+            self.free.push(entity.index);
+            *self.free_cursor.get_mut() = self.free.len() as i64;
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::Entity;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NecsError {
+    NoSuchEntity(Entity),
+    NotReserved(Entity),
+    ComponentMissing { entity: Entity, type_name: &'static str },
+    ComponentAlreadyPresent { entity: Entity, type_name: &'static str },
+    StorageTypeMismatch { type_name: &'static str },
+    UnknownLabel { label: String },
+    ScheduleCycle { systems: Vec<String> },
+}
+
+impl fmt::Display for NecsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NecsError::NoSuchEntity(entity) => write!(f, "entity {:?} does not exist", entity),
+            NecsError::NotReserved(entity) => write!(f, "entity {:?} is not reserved", entity),
+            NecsError::ComponentMissing { entity, type_name } => write!(f, "entity {:?} has no {} component", entity, type_name),
+            NecsError::ComponentAlreadyPresent { entity, type_name } => write!(f, "entity {:?} already has a {} component", entity, type_name),
+            NecsError::StorageTypeMismatch { type_name } => write!(f, "storage of {} holds another type", type_name),
+            NecsError::UnknownLabel { label } => write!(f, "no system is labelled {:?} in the stage", label),
+            NecsError::ScheduleCycle { systems } => write!(f, "systems {} are ordered in a cycle", systems.join(", ")),
+        }
+    }
+}
+
+impl Error for NecsError {}
+
+/// A component that could not be added, handed back along with the reason.
+pub struct Rejected<T> {
+    pub error: NecsError,
+    pub component: T,
+}
+
+impl<T> fmt::Debug for Rejected<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rejected").field("error", &self.error).finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for Rejected<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<T> Error for Rejected<T> {}
+
+impl<T> From<Rejected<T>> for NecsError {
+    fn from(rejected: Rejected<T>) -> Self {
+        rejected.error
+    }
+}
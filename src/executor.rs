@@ -0,0 +1,89 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::{Registry, System};
+
+/// How a [`Schedule`](crate::Schedule) runs the systems of a stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Executor {
+    /// One system after the other, in order; deterministic, for debugging and tests.
+    SingleThreaded,
+    /// Systems whose access does not conflict run concurrently on a pool of `threads` worker threads, started on the
+    /// first run of the schedule and kept until it is dropped or its executor changes.
+    Parallel { threads: usize },
+}
+
+impl Executor {
+    pub fn parallel() -> Self {
+        Executor::Parallel { threads: thread::available_parallelism().map_or(1, |threads| threads.get()) }
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Executor::parallel()
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+/// A job and where to report how it ended.
+type Task = (Job, Sender<thread::Result<()>>);
+
+/// Worker threads taking jobs in the order they were sent, each reporting on its own channel once it returned.
+pub(crate) struct ThreadPool {
+    sender: Option<Sender<Task>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    pub fn new(threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Task>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads.max(1)).map(|_| {
+            let receiver = receiver.clone();
+            thread::spawn(move || loop {
+                let Ok((job, done)) = receiver.lock().unwrap().recv() else { break };
+                let _ = done.send(panic::catch_unwind(AssertUnwindSafe(job)));
+            })
+        }).collect();
+        Self { sender: Some(sender), workers }
+    }
+
+    /// Runs a batch of non-exclusive, mutually compatible systems on the workers, returning once all of them are
+    /// done. A panic of one of them is resumed afterwards.
+    pub fn run_batch(&self, systems: Vec<&mut Box<dyn System>>, registry: &Registry) {
+        let (done, results) = mpsc::channel();
+        for system in systems {
+            // SAFETY: the systems of a batch have compatible access and none of them is exclusive
+            let job: Box<dyn FnOnce() + Send + '_> = Box::new(move || unsafe { system.run_unchecked(registry) });
+            // SAFETY: the job borrows the systems and the registry until it returns, and nothing below returns or unwinds
+            // before every job has returned and its end of `done` was dropped
+            let job: Job = unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + '_>, Job>(job) };
+            if let Err(mpsc::SendError((job, done))) = self.sender.as_ref().unwrap().send((job, done.clone())) {
+                let _ = done.send(panic::catch_unwind(AssertUnwindSafe(job)));
+            }
+        }
+        drop(done);
+        let mut panicked = None;
+        for result in results {
+            if let Err(payload) = result {
+                panicked.get_or_insert(payload);
+            }
+        }
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Closing the channel lets the workers finish
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
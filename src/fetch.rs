@@ -0,0 +1,334 @@
+use std::any::{type_name, TypeId};
+use std::collections::HashSet;
+
+use crate::archetype::Archetypes;
+use crate::hierarchy;
+use crate::storage::{ComponentPtr, ComponentPtrs, SparseSetPtr};
+use crate::{Archetype, ArchetypeId, ComponentId, ComponentTrait, Entity, Registry, Resource, StorageKind, Tick};
+
+/// A term of a view query, resolved once against the registry into a `State` and then probed per entity.
+///
+/// Entities are probed with the `row` they are at in the storage the view walks: in the archetype last passed to
+/// `set_archetype` when the view walks archetype tables, in the sparse set or the group otherwise. Entities looked up
+/// directly come with no row.
+///
+/// # Safety
+/// `access` must declare every component type the term fetches, and whether it is fetched mutably.
+pub unsafe trait Fetch {
+    type Item<'r>;
+    type State<'r>: Copy;
+    fn access(access: &mut Access);
+    /// `last_run` is the tick change filters compare against.
+    fn init(registry: &Registry, last_run: Tick) -> Option<Self::State<'_>>;
+    /// The entities this term requires, if any; the view walks the smallest of them, or every entity when no term
+    /// requires any.
+    fn candidates<'r>(state: &Self::State<'r>) -> Option<Candidates<'r>>;
+    /// Whether entities of the archetype may match; terms not stored in tables accept every archetype.
+    fn matches_archetype(_state: &Self::State<'_>, _archetype: &Archetype) -> bool { true }
+    /// Points the state at the archetype whose rows the view walks next.
+    fn set_archetype<'r>(_state: &mut Self::State<'r>, _archetype: &'r Archetype) {}
+    fn matches(state: &Self::State<'_>, entity: Entity, row: Option<usize>) -> bool;
+    /// # Safety
+    /// `entity` must match, and the storages declared as written by `access` must not be borrowed elsewhere,
+    /// including by items previously fetched for the same entity.
+    unsafe fn fetch<'r>(state: &Self::State<'r>, entity: Entity, row: Option<usize>) -> Self::Item<'r>;
+}
+
+/// The entities a view walks: the ones of a sparse set, or the rows of archetype tables.
+#[derive(Clone, Copy)]
+pub enum Candidates<'r> {
+    Entities(&'r [Entity]),
+    Archetypes(&'r Archetypes, &'r [ArchetypeId]),
+}
+
+impl Candidates<'_> {
+    pub fn len(&self) -> usize {
+        match self {
+            Candidates::Entities(entities) => entities.len(),
+            Candidates::Archetypes(archetypes, ids) => ids.iter().map(|id| archetypes.get(*id).len()).sum(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The components of type `T` as a view sees them: a sparse or tag set, or the columns of the archetypes having `T`.
+pub enum Source<'r, T> {
+    Set(SparseSetPtr<'r, T>),
+    /// `column` is the one of the archetype last set, if any, when it has `T`.
+    Table { archetypes: &'r Archetypes, column: Option<Option<ComponentPtrs<T>>> },
+}
+
+impl<T> Clone for Source<'_, T> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<T> Copy for Source<'_, T> {}
+
+impl<'r, T: ComponentTrait> Source<'r, T> {
+    pub(crate) fn init(registry: &'r Registry) -> Option<Self> {
+        match registry.storage_kind::<T>() {
+            StorageKind::SparseSet => registry.storage::<T>().map(|storage| Source::Set(storage.ptr())),
+            StorageKind::Tag => registry.tag_storage::<T>().map(|storage| Source::Set(storage.ptr())),
+            StorageKind::Table => {
+                let archetypes = &registry.archetypes;
+                let stored = !archetypes.with_component(TypeId::of::<T>()).is_empty();
+                stored.then_some(Source::Table { archetypes, column: None })
+            }
+        }
+    }
+
+    pub(crate) fn candidates(&self) -> Candidates<'r> {
+        match *self {
+            Source::Set(set) => Candidates::Entities(set.entities()),
+            Source::Table { archetypes, .. } => Candidates::Archetypes(archetypes, archetypes.with_component(TypeId::of::<T>())),
+        }
+    }
+
+    /// Whether the archetype is known to hold `T`, or known not to when `stored` is false.
+    pub(crate) fn matches_archetype(&self, archetype: &Archetype, stored: bool) -> bool {
+        match self {
+            Source::Set(..) => true,
+            Source::Table { .. } => archetype.contains(TypeId::of::<T>()) == stored,
+        }
+    }
+
+    pub(crate) fn set_archetype(&mut self, archetype: &'r Archetype) {
+        if let Source::Table { column, .. } = self {
+            *column = Some(archetype.column_ptrs::<T>());
+        }
+    }
+
+    /// Dereferencing the pointers is up to the caller. Sets take `row` as a hint, which pays off for the one the view
+    /// walks and the ones a group packs alike.
+    pub(crate) fn component(&self, entity: Entity, row: Option<usize>) -> Option<ComponentPtr<T>> {
+        match (*self, row) {
+            (Source::Set(set), _) => set.component_ptr(entity, row),
+            // SAFETY: the view walks the rows of the archetype the column belongs to
+            (Source::Table { column: Some(column), .. }, Some(row)) => column.map(|column| unsafe { column.at(row) }),
+            (Source::Table { archetypes, .. }, _) => archetypes.component_ptr(entity),
+        }
+    }
+}
+
+/// # Safety
+/// The term must not fetch anything mutably.
+pub unsafe trait ReadOnlyFetch: Fetch {}
+
+/// The component and resource types a query reads and writes.
+#[derive(Debug, Default, Clone)]
+pub struct Access {
+    reads: HashSet<ComponentId>,
+    writes: HashSet<ComponentId>,
+    filters: HashSet<ComponentId>,
+    resource_reads: HashSet<TypeId>,
+    resource_writes: HashSet<TypeId>,
+    conflicts: Vec<&'static str>,
+}
+
+impl Access {
+    pub(crate) fn of<F: Fetch>() -> Self {
+        let mut access = Access::default();
+        F::access(&mut access);
+        access
+    }
+
+    pub fn read<Component: ComponentTrait>(&mut self) {
+        let component_id = TypeId::of::<Component>();
+        if self.writes.contains(&component_id) {
+            self.conflicts.push(type_name::<Component>());
+        }
+        self.reads.insert(component_id);
+    }
+
+    /// Requires the component without fetching it: other queries see a read, while fetching it mutably in the same
+    /// query is fine.
+    pub fn filter<Component: ComponentTrait>(&mut self) {
+        self.filters.insert(TypeId::of::<Component>());
+    }
+
+    /// Panics for [`Parent`](crate::Parent) and [`Children`](crate::Children), which only the registry writes.
+    pub fn write<Component: ComponentTrait>(&mut self) {
+        let component_id = TypeId::of::<Component>();
+        assert!(!hierarchy::is_link(component_id), "write: component {} is maintained by the registry", type_name::<Component>());
+        if self.reads.contains(&component_id) || !self.writes.insert(component_id) {
+            self.conflicts.push(type_name::<Component>());
+        }
+    }
+
+    pub fn read_resource<R: Resource>(&mut self) {
+        let resource_id = TypeId::of::<R>();
+        if self.resource_writes.contains(&resource_id) {
+            self.conflicts.push(type_name::<R>());
+        }
+        self.resource_reads.insert(resource_id);
+    }
+
+    pub fn write_resource<R: Resource>(&mut self) {
+        let resource_id = TypeId::of::<R>();
+        if self.resource_reads.contains(&resource_id) || !self.resource_writes.insert(resource_id) {
+            self.conflicts.push(type_name::<R>());
+        }
+    }
+
+    pub fn reads(&self) -> impl Iterator<Item = &ComponentId> { self.reads.iter() }
+    pub fn writes(&self) -> impl Iterator<Item = &ComponentId> { self.writes.iter() }
+    pub fn filters(&self) -> impl Iterator<Item = &ComponentId> { self.filters.iter() }
+
+    pub fn resource_reads(&self) -> impl Iterator<Item = &TypeId> { self.resource_reads.iter() }
+    pub fn resource_writes(&self) -> impl Iterator<Item = &TypeId> { self.resource_writes.iter() }
+
+    /// Names of the component and resource types that were requested mutably together with any other access.
+    pub fn conflicts(&self) -> &[&'static str] { &self.conflicts }
+
+    pub fn is_compatible(&self, other: &Access) -> bool {
+        self.writes.is_disjoint(&other.reads) && self.writes.is_disjoint(&other.writes) && self.reads.is_disjoint(&other.writes)
+            && self.writes.is_disjoint(&other.filters) && self.filters.is_disjoint(&other.writes)
+            && self.resource_writes.is_disjoint(&other.resource_reads) && self.resource_writes.is_disjoint(&other.resource_writes)
+            && self.resource_reads.is_disjoint(&other.resource_writes)
+    }
+}
+
+/// Matches every entity without fetching anything, and requires nothing.
+unsafe impl Fetch for () {
+    type Item<'r> = ();
+    type State<'r> = ();
+
+    fn access(_access: &mut Access) {}
+    fn init(_registry: &Registry, _last_run: Tick) -> Option<Self::State<'_>> { Some(()) }
+    fn candidates<'r>(_state: &Self::State<'r>) -> Option<Candidates<'r>> { None }
+    fn matches(_state: &Self::State<'_>, _entity: Entity, _row: Option<usize>) -> bool { true }
+    unsafe fn fetch<'r>(_state: &Self::State<'r>, _entity: Entity, _row: Option<usize>) -> Self::Item<'r> {}
+}
+
+unsafe impl ReadOnlyFetch for () {}
+
+unsafe impl<T: ComponentTrait> Fetch for &T {
+    type Item<'r> = &'r T;
+    type State<'r> = Source<'r, T>;
+
+    fn access(access: &mut Access) { access.read::<T>(); }
+    fn init(registry: &Registry, _last_run: Tick) -> Option<Self::State<'_>> { Source::init(registry) }
+    fn candidates<'r>(state: &Self::State<'r>) -> Option<Candidates<'r>> { Some(state.candidates()) }
+    fn matches_archetype(state: &Self::State<'_>, archetype: &Archetype) -> bool { state.matches_archetype(archetype, true) }
+    fn set_archetype<'r>(state: &mut Self::State<'r>, archetype: &'r Archetype) { state.set_archetype(archetype); }
+    fn matches(state: &Self::State<'_>, entity: Entity, row: Option<usize>) -> bool { state.component(entity, row).is_some() }
+
+    unsafe fn fetch<'r>(state: &Self::State<'r>, entity: Entity, row: Option<usize>) -> Self::Item<'r> {
+        state.component(entity, row).unwrap().get()
+    }
+}
+
+unsafe impl<T: ComponentTrait> ReadOnlyFetch for &T {}
+
+unsafe impl<T: ComponentTrait> Fetch for &mut T {
+    type Item<'r> = &'r mut T;
+    type State<'r> = (Source<'r, T>, Tick);
+
+    fn access(access: &mut Access) { access.write::<T>(); }
+    fn init(registry: &Registry, _last_run: Tick) -> Option<Self::State<'_>> { Some((Source::init(registry)?, registry.change_tick())) }
+    fn candidates<'r>(state: &Self::State<'r>) -> Option<Candidates<'r>> { Some(state.0.candidates()) }
+    fn matches_archetype(state: &Self::State<'_>, archetype: &Archetype) -> bool { state.0.matches_archetype(archetype, true) }
+    fn set_archetype<'r>(state: &mut Self::State<'r>, archetype: &'r Archetype) { state.0.set_archetype(archetype); }
+    fn matches(state: &Self::State<'_>, entity: Entity, row: Option<usize>) -> bool { state.0.component(entity, row).is_some() }
+
+    /// Handing out the component mutably stamps it as changed, whether or not it is written to.
+    unsafe fn fetch<'r>(state: &Self::State<'r>, entity: Entity, row: Option<usize>) -> Self::Item<'r> {
+        state.0.component(entity, row).unwrap().get_mut(state.1)
+    }
+}
+
+/// Fetches the term when the entity matches it, without requiring it to.
+unsafe impl<F: Fetch> Fetch for Option<F> {
+    type Item<'r> = Option<F::Item<'r>>;
+    type State<'r> = Option<F::State<'r>>;
+
+    fn access(access: &mut Access) { F::access(access); }
+    fn init(registry: &Registry, last_run: Tick) -> Option<Self::State<'_>> { Some(F::init(registry, last_run)) }
+    fn candidates<'r>(_state: &Self::State<'r>) -> Option<Candidates<'r>> { None }
+
+    fn set_archetype<'r>(state: &mut Self::State<'r>, archetype: &'r Archetype) {
+        if let Some(state) = state {
+            F::set_archetype(state, archetype);
+        }
+    }
+
+    fn matches(_state: &Self::State<'_>, _entity: Entity, _row: Option<usize>) -> bool { true }
+
+    unsafe fn fetch<'r>(state: &Self::State<'r>, entity: Entity, row: Option<usize>) -> Self::Item<'r> {
+        match state {
+            Some(state) if F::matches(state, entity, row) => Some(F::fetch(state, entity, row)),
+            _ => None,
+        }
+    }
+}
+
+unsafe impl<F: ReadOnlyFetch> ReadOnlyFetch for Option<F> {}
+
+macro_rules! impl_fetch_tuple {
+    ( $( $F:ident ),+ ) => {
+        #[allow(non_snake_case)]
+        unsafe impl<$( $F: Fetch ),+> Fetch for ( $( $F, )+ ) {
+            type Item<'r> = ( $( $F::Item<'r>, )+ );
+            type State<'r> = ( $( $F::State<'r>, )+ );
+
+            fn access(access: &mut Access) {
+                $( $F::access(access); )+
+            }
+
+            fn init(registry: &Registry, last_run: Tick) -> Option<Self::State<'_>> {
+                Some(( $( $F::init(registry, last_run)?, )+ ))
+            }
+
+            fn candidates<'r>(state: &Self::State<'r>) -> Option<Candidates<'r>> {
+                let ( $( $F, )+ ) = state;
+                let mut smallest: Option<(Candidates<'r>, usize)> = None;
+                $(
+                    if let Some(candidates) = $F::candidates($F) {
+                        let len = candidates.len();
+                        if smallest.map_or(true, |(_, smallest)| len < smallest) {
+                            smallest = Some((candidates, len));
+                        }
+                    }
+                )+
+                smallest.map(|(candidates, _)| candidates)
+            }
+
+            fn matches_archetype(state: &Self::State<'_>, archetype: &Archetype) -> bool {
+                let ( $( $F, )+ ) = state;
+                $( $F::matches_archetype($F, archetype) )&&+
+            }
+
+            fn set_archetype<'r>(state: &mut Self::State<'r>, archetype: &'r Archetype) {
+                let ( $( $F, )+ ) = state;
+                $( $F::set_archetype($F, archetype); )+
+            }
+
+            fn matches(state: &Self::State<'_>, entity: Entity, row: Option<usize>) -> bool {
+                let ( $( $F, )+ ) = state;
+                $( $F::matches($F, entity, row) )&&+
+            }
+
+            unsafe fn fetch<'r>(state: &Self::State<'r>, entity: Entity, row: Option<usize>) -> Self::Item<'r> {
+                let ( $( $F, )+ ) = state;
+                ( $( $F::fetch($F, entity, row), )+ )
+            }
+        }
+
+        unsafe impl<$( $F: ReadOnlyFetch ),+> ReadOnlyFetch for ( $( $F, )+ ) {}
+    }
+}
+
+macro_rules! impl_fetch_tuple_expand {
+    ( $F:ident ) => {
+        impl_fetch_tuple!($F);
+    };
+    ( $F:ident, $( $Fs:ident ),+ ) => {
+        impl_fetch_tuple!($F, $( $Fs ),+);
+        impl_fetch_tuple_expand!($( $Fs ),+);
+    };
+}
+
+impl_fetch_tuple_expand!(L, K, J, I, H, G, F, E, D, C, B, A);
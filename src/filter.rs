@@ -0,0 +1,102 @@
+use std::marker::PhantomData;
+
+use crate::fetch::Source;
+use crate::{Access, Archetype, Candidates, ComponentTrait, Entity, Fetch, ReadOnlyFetch, Registry, Tick};
+
+/// Keeps the filters from being `Send` and `Sync`, so that they are not components either: passing one where
+/// components are expected, as to [`Registry::view_all`], fails to compile rather than matching nothing.
+struct NotAComponent(PhantomData<*const ()>);
+
+/// Requires the component without fetching it.
+pub struct With<T>(PhantomData<T>, NotAComponent);
+
+/// Excludes entities that have the component.
+pub struct Without<T>(PhantomData<T>, NotAComponent);
+
+/// Requires the component to have been added since the last run.
+pub struct Added<T>(PhantomData<T>, NotAComponent);
+
+/// Requires the component to have been added or changed since the last run.
+pub struct Changed<T>(PhantomData<T>, NotAComponent);
+
+unsafe impl<T: ComponentTrait> Fetch for With<T> {
+    type Item<'r> = ();
+    type State<'r> = Source<'r, T>;
+
+    fn access(access: &mut Access) { access.filter::<T>(); }
+    fn init(registry: &Registry, _last_run: Tick) -> Option<Self::State<'_>> { Source::init(registry) }
+    fn candidates<'r>(state: &Self::State<'r>) -> Option<Candidates<'r>> { Some(state.candidates()) }
+    fn matches_archetype(state: &Self::State<'_>, archetype: &Archetype) -> bool { state.matches_archetype(archetype, true) }
+    fn set_archetype<'r>(state: &mut Self::State<'r>, archetype: &'r Archetype) { state.set_archetype(archetype); }
+    fn matches(state: &Self::State<'_>, entity: Entity, row: Option<usize>) -> bool { state.component(entity, row).is_some() }
+    unsafe fn fetch<'r>(_state: &Self::State<'r>, _entity: Entity, _row: Option<usize>) -> Self::Item<'r> {}
+}
+
+unsafe impl<T: ComponentTrait> ReadOnlyFetch for With<T> {}
+
+unsafe impl<T: ComponentTrait> Fetch for Without<T> {
+    type Item<'r> = ();
+    type State<'r> = Option<Source<'r, T>>;
+
+    fn access(access: &mut Access) { access.filter::<T>(); }
+    fn init(registry: &Registry, _last_run: Tick) -> Option<Self::State<'_>> { Some(Source::init(registry)) }
+    fn candidates<'r>(_state: &Self::State<'r>) -> Option<Candidates<'r>> { None }
+
+    fn matches_archetype(state: &Self::State<'_>, archetype: &Archetype) -> bool {
+        state.is_none_or(|state| state.matches_archetype(archetype, false))
+    }
+
+    fn set_archetype<'r>(state: &mut Self::State<'r>, archetype: &'r Archetype) {
+        if let Some(state) = state {
+            state.set_archetype(archetype);
+        }
+    }
+
+    fn matches(state: &Self::State<'_>, entity: Entity, row: Option<usize>) -> bool {
+        !state.is_some_and(|state| state.component(entity, row).is_some())
+    }
+
+    unsafe fn fetch<'r>(_state: &Self::State<'r>, _entity: Entity, _row: Option<usize>) -> Self::Item<'r> {}
+}
+
+unsafe impl<T: ComponentTrait> ReadOnlyFetch for Without<T> {}
+
+unsafe impl<T: ComponentTrait> Fetch for Added<T> {
+    type Item<'r> = ();
+    type State<'r> = (Source<'r, T>, Tick);
+
+    fn access(access: &mut Access) { access.filter::<T>(); }
+    fn init(registry: &Registry, last_run: Tick) -> Option<Self::State<'_>> { Some((Source::init(registry)?, last_run)) }
+    fn candidates<'r>(state: &Self::State<'r>) -> Option<Candidates<'r>> { Some(state.0.candidates()) }
+    fn matches_archetype(state: &Self::State<'_>, archetype: &Archetype) -> bool { state.0.matches_archetype(archetype, true) }
+    fn set_archetype<'r>(state: &mut Self::State<'r>, archetype: &'r Archetype) { state.0.set_archetype(archetype); }
+
+    fn matches(state: &Self::State<'_>, entity: Entity, row: Option<usize>) -> bool {
+        // SAFETY: the ticks of components fetched mutably are only written while fetching, not while matching
+        state.0.component(entity, row).is_some_and(|component| unsafe { component.ticks() }.is_added(state.1))
+    }
+
+    unsafe fn fetch<'r>(_state: &Self::State<'r>, _entity: Entity, _row: Option<usize>) -> Self::Item<'r> {}
+}
+
+unsafe impl<T: ComponentTrait> ReadOnlyFetch for Added<T> {}
+
+unsafe impl<T: ComponentTrait> Fetch for Changed<T> {
+    type Item<'r> = ();
+    type State<'r> = (Source<'r, T>, Tick);
+
+    fn access(access: &mut Access) { access.filter::<T>(); }
+    fn init(registry: &Registry, last_run: Tick) -> Option<Self::State<'_>> { Some((Source::init(registry)?, last_run)) }
+    fn candidates<'r>(state: &Self::State<'r>) -> Option<Candidates<'r>> { Some(state.0.candidates()) }
+    fn matches_archetype(state: &Self::State<'_>, archetype: &Archetype) -> bool { state.0.matches_archetype(archetype, true) }
+    fn set_archetype<'r>(state: &mut Self::State<'r>, archetype: &'r Archetype) { state.0.set_archetype(archetype); }
+
+    fn matches(state: &Self::State<'_>, entity: Entity, row: Option<usize>) -> bool {
+        // SAFETY: as for `Added`
+        state.0.component(entity, row).is_some_and(|component| unsafe { component.ticks() }.is_changed(state.1))
+    }
+
+    unsafe fn fetch<'r>(_state: &Self::State<'r>, _entity: Entity, _row: Option<usize>) -> Self::Item<'r> {}
+}
+
+unsafe impl<T: ComponentTrait> ReadOnlyFetch for Changed<T> {}
@@ -0,0 +1,170 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::storage::{IterationOrder, SparseSet, StorageCell};
+use crate::{Access, Candidates, ComponentId, Entity, Fetch, ReadOnlyFetch, Registry, View};
+
+/// Who belongs to a group.
+enum Members {
+    /// How many entities are packed at the front of the owned storages.
+    Packed(usize),
+    /// The matching entities, for groups owning nothing.
+    Set(SparseSet<()>),
+}
+
+/// Definition of a group, along with its members.
+///
+/// The entities matching an owning group are kept at the front of every owned sparse set, in the same order, so
+/// walking the group reads the owned components of the `i`-th entity at index `i` of each storage.
+pub(crate) struct GroupData {
+    owned: Vec<ComponentId>,
+    get: Vec<ComponentId>,
+    exclude: Vec<ComponentId>,
+    /// Type names of `owned`, `get` and `exclude`, one after the other.
+    type_names: Vec<&'static str>,
+    members: Members,
+}
+
+impl GroupData {
+    pub fn new(owned: Vec<ComponentId>, get: Vec<ComponentId>, exclude: Vec<ComponentId>, type_names: Vec<&'static str>) -> Self {
+        let members = if owned.is_empty() { Members::Set(SparseSet::new()) } else { Members::Packed(0) };
+        Self { owned, get, exclude, type_names, members }
+    }
+
+    pub fn owned(&self) -> &[ComponentId] { &self.owned }
+
+    pub fn len(&self) -> usize {
+        match &self.members {
+            Members::Packed(len) => *len,
+            Members::Set(entities) => entities.len(),
+        }
+    }
+
+    pub fn is(&self, owned: &[ComponentId], get: &[ComponentId], exclude: &[ComponentId]) -> bool {
+        same(&self.owned, owned) && same(&self.get, get) && same(&self.exclude, exclude)
+    }
+
+    /// Whether the group holds exactly the entities having all of the components.
+    pub fn caches(&self, component_ids: &[ComponentId]) -> bool {
+        self.exclude.is_empty() && self.owned.len() + self.get.len() == component_ids.len()
+            && component_ids.iter().all(|id| self.owned.contains(id) || self.get.contains(id))
+    }
+
+    pub fn involves(&self, component_id: ComponentId) -> bool {
+        self.owned.contains(&component_id) || self.get.contains(&component_id) || self.exclude.contains(&component_id)
+    }
+
+    /// The members, as held by the first owned storage for owning groups.
+    pub fn entities<'r>(&'r self, pool: &'r HashMap<ComponentId, StorageCell>) -> &'r [Entity] {
+        match &self.members {
+            Members::Packed(len) => pool.get(&self.owned[0]).map_or(&[], |cell| &cell.get().entities()[..*len]),
+            Members::Set(entities) => entities.entities(),
+        }
+    }
+
+    pub fn contains(&self, entity: Entity, pool: &HashMap<ComponentId, StorageCell>) -> bool {
+        match &self.members {
+            Members::Packed(len) => pool.get(&self.owned[0]).and_then(|cell| cell.get().index_of(&entity)).is_some_and(|index| index < *len),
+            Members::Set(entities) => entities.contains(entity),
+        }
+    }
+
+    /// Adds the entity when it now matches the group, given the components it has, or drops it when it no longer
+    /// does. For owning groups, dropping must happen before an owned component is removed from its storage.
+    pub fn refresh(&mut self, entity: Entity, component_ids: &HashSet<ComponentId>, pool: &mut HashMap<ComponentId, StorageCell>, order: IterationOrder) {
+        let matches = self.owned.iter().chain(&self.get).all(|id| component_ids.contains(id))
+            && !self.exclude.iter().any(|id| component_ids.contains(id));
+        if matches == self.contains(entity, pool) {
+            return;
+        }
+        match &mut self.members {
+            Members::Packed(len) => {
+                if !matches {
+                    *len -= 1;
+                }
+                for id in &self.owned {
+                    let storage = pool.get_mut(id).unwrap().get_mut();
+                    let index = storage.index_of(&entity).unwrap();
+                    storage.swap(index, *len);
+                }
+                if matches {
+                    *len += 1;
+                }
+            }
+            Members::Set(entities) if matches => { entities.insert(entity, (), 0); }
+            Members::Set(entities) => { entities.remove_in_order(entity, order); }
+        }
+    }
+
+    /// Puts the members of a group owning nothing in the order of `entities`, the storage of `component_id`, if that
+    /// is the first component the group gets, so that walking the group follows how that storage was sorted.
+    pub fn follow(&mut self, component_id: ComponentId, entities: &[Entity]) {
+        let Members::Set(members) = &mut self.members else { return };
+        if self.get.first() == Some(&component_id) {
+            let mut sorted = SparseSet::new();
+            for entity in entities.iter().filter(|entity| members.contains(**entity)) {
+                sorted.insert(*entity, (), 0);
+            }
+            *members = sorted;
+        }
+    }
+
+    pub fn info(&self) -> GroupInfo {
+        let (owned, rest) = self.type_names.split_at(self.owned.len());
+        let (get, exclude) = rest.split_at(self.get.len());
+        GroupInfo { owned: owned.to_vec(), get: get.to_vec(), exclude: exclude.to_vec(), len: self.len() }
+    }
+}
+
+fn same(a: &[ComponentId], b: &[ComponentId]) -> bool {
+    a.len() == b.len() && a.iter().all(|id| b.contains(id))
+}
+
+/// What a group is made of and how many entities it holds, as listed by [`Registry::groups`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupInfo {
+    pub owned: Vec<&'static str>,
+    pub get: Vec<&'static str>,
+    pub exclude: Vec<&'static str>,
+    pub len: usize,
+}
+
+/// A group, see [`Registry::group`].
+pub struct Group<'r> {
+    registry: &'r mut Registry,
+    group: usize,
+}
+
+impl<'r> Group<'r> {
+    pub(crate) fn new(registry: &'r mut Registry, group: usize) -> Self {
+        Self { registry, group }
+    }
+
+    fn data(&self) -> &GroupData { &self.registry.groups[self.group] }
+
+    pub fn len(&self) -> usize { self.data().len() }
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// The entities of the group, in the order the owned storages hold them for owning groups.
+    pub fn entities(&self) -> &[Entity] {
+        self.data().entities(&self.registry.component_pool)
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.data().contains(entity, &self.registry.component_pool)
+    }
+
+    /// Walks the entities of the group, fetching the owned components of `Query` side by side.
+    pub fn view<Query: ReadOnlyFetch>(&self) -> View<'_, Query> {
+        // SAFETY: nothing is fetched mutably
+        unsafe { View::with_candidates(self.registry, self.registry.last_change_tick(), Candidates::Entities(self.entities())) }
+    }
+
+    pub fn view_mut<Query: Fetch>(&mut self) -> View<'_, Query> {
+        if let Some(component) = Access::of::<Query>().conflicts().first() {
+            panic!("view_mut: component {} is borrowed mutably more than once", component);
+        }
+        let candidates = Candidates::Entities(self.data().entities(&self.registry.component_pool));
+        // SAFETY: the registry is borrowed exclusively and the query never aliases a mutably fetched component
+        unsafe { View::with_candidates(self.registry, self.registry.last_change_tick(), candidates) }
+    }
+}
@@ -0,0 +1,147 @@
+use std::any::TypeId;
+use std::collections::VecDeque;
+use std::ops::Deref;
+
+use crate::{ComponentId, Entity, Event, Registry};
+
+/// The parent of an entity, set by [`Registry::set_parent`]. Neither cloneable nor constructible outside the crate,
+/// and neither views, systems nor patches hand it out mutably, so that it always matches the [`Children`] of the parent.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Parent(Entity);
+
+impl Parent {
+    pub fn get(&self) -> Entity { self.0 }
+}
+
+/// The children of an entity, in the order they were attached. Like [`Parent`], only the registry changes it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Children(Vec<Entity>);
+
+impl Deref for Children {
+    type Target = [Entity];
+
+    fn deref(&self) -> &[Entity] {
+        &self.0
+    }
+}
+
+impl Registry {
+    /// Attaches `child` to `parent`, detaching it from its previous parent.
+    ///
+    /// Panics if either entity does not exist, or if `parent` is `child` or one of its descendants.
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) {
+        assert!(self.exists(child) && self.exists(parent), "set_parent: entity {:?} or {:?} does not exist", child, parent);
+        let mut ancestor = Some(parent);
+        while let Some(entity) = ancestor {
+            assert!(entity != child, "set_parent: entity {:?} cannot be attached to itself or its descendant {:?}", child, parent);
+            ancestor = self.parent(entity);
+        }
+        if self.parent(child) == Some(parent) {
+            return;
+        }
+        self.remove::<Parent>(child);
+        self.add(child, Parent(parent));
+        match self.get_mut_changed::<Children>(parent) {
+            Some(children) => {
+                children.0.push(child);
+                self.notify(TypeId::of::<Children>(), Event::Update, parent);
+            }
+            None => self.add(parent, Children(vec![child])),
+        }
+    }
+
+    /// Detaches `child` from its parent, if any.
+    pub fn remove_parent(&mut self, child: Entity) {
+        self.remove::<Parent>(child);
+    }
+
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        self.get::<Parent>(entity).map(Parent::get)
+    }
+
+    pub fn children(&self, entity: Entity) -> impl Iterator<Item = Entity> + '_ {
+        self.get::<Children>(entity).map_or(&[][..], |children| &children.0).iter().copied()
+    }
+
+    /// The descendants of `entity`, each followed by its own descendants before its next sibling.
+    pub fn descendants_depth_first(&self, entity: Entity) -> DepthFirst<'_> {
+        let mut stack: Vec<Entity> = self.children(entity).collect();
+        stack.reverse();
+        DepthFirst { registry: self, stack }
+    }
+
+    /// The descendants of `entity`, level by level.
+    pub fn descendants_breadth_first(&self, entity: Entity) -> BreadthFirst<'_> {
+        BreadthFirst { registry: self, queue: self.children(entity).collect() }
+    }
+
+    /// Destroys the entity along with all its descendants, deepest first.
+    pub fn destroy_recursive(&mut self, entity: Entity) {
+        let descendants: Vec<Entity> = self.descendants_depth_first(entity).collect();
+        for descendant in descendants.into_iter().rev() {
+            self.destroy(descendant);
+        }
+        self.destroy(entity);
+    }
+
+    /// Keeps both sides of a relationship in sync when one of them is about to be removed, destroyed or not.
+    pub(crate) fn unlink_hierarchy(&mut self, entity: Entity, component_id: ComponentId) {
+        if component_id == TypeId::of::<Parent>() {
+            let parent = self.parent(entity).unwrap();
+            let Some(index) = self.children(parent).position(|child| child == entity) else { return };
+            let children = &mut self.get_mut_changed::<Children>(parent).unwrap().0;
+            children.remove(index);
+            if children.is_empty() {
+                self.remove::<Children>(parent);
+            } else {
+                self.notify(TypeId::of::<Children>(), Event::Update, parent);
+            }
+        } else if component_id == TypeId::of::<Children>() {
+            // Emptied first, so the children leaving do not remove the component a second time
+            let children = std::mem::take(&mut self.get_mut_changed::<Children>(entity).unwrap().0);
+            for child in children {
+                self.remove::<Parent>(child);
+            }
+        }
+    }
+}
+
+/// Whether the component is a side of a relationship, which is never handed out mutably: swapping two of them would
+/// leave parents and children disagreeing.
+pub(crate) fn is_link(component_id: ComponentId) -> bool {
+    component_id == TypeId::of::<Parent>() || component_id == TypeId::of::<Children>()
+}
+
+/// Depth-first walk of the descendants of an entity, see [`Registry::descendants_depth_first`].
+pub struct DepthFirst<'r> {
+    registry: &'r Registry,
+    stack: Vec<Entity>,
+}
+
+impl Iterator for DepthFirst<'_> {
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Entity> {
+        let entity = self.stack.pop()?;
+        let first = self.stack.len();
+        self.stack.extend(self.registry.children(entity));
+        self.stack[first..].reverse();
+        Some(entity)
+    }
+}
+
+/// Breadth-first walk of the descendants of an entity, see [`Registry::descendants_breadth_first`].
+pub struct BreadthFirst<'r> {
+    registry: &'r Registry,
+    queue: VecDeque<Entity>,
+}
+
+impl Iterator for BreadthFirst<'_> {
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Entity> {
+        let entity = self.queue.pop_front()?;
+        self.queue.extend(self.registry.children(entity));
+        Some(entity)
+    }
+}
@@ -1,55 +1,146 @@
 #![allow(dead_code)]
 
-use std::any::{Any, TypeId};
+use std::any::{type_name, TypeId};
+use std::cmp::Ordering;
 use std::collections::{HashSet, HashMap};
-use std::collections::hash_map::Entry;
+use std::mem::size_of;
+
+mod archetype;
+mod cell;
+mod commands;
+mod entity;
+mod error;
+mod executor;
+mod fetch;
+mod filter;
+mod group;
+mod hierarchy;
+mod observer;
+mod patch;
+mod resource;
+mod schedule;
+mod storage;
+mod system;
+mod tick;
+mod view;
 
 #[cfg(test)]
 mod tests;
 
-pub type Entity = u64;
+pub use archetype::{Archetype, ArchetypeId};
+use archetype::Archetypes;
+pub use commands::Commands;
+pub use entity::Entity;
+use entity::EntityAllocator;
+pub use error::{NecsError, Rejected};
+pub use executor::Executor;
+pub use fetch::{Access, Candidates, Fetch, ReadOnlyFetch};
+pub use filter::{Added, Changed, With, Without};
+pub use group::{Group, GroupInfo};
+use group::GroupData;
+pub use hierarchy::{BreadthFirst, Children, DepthFirst, Parent};
+pub use observer::{CollectorBuilder, Connection, Event, Listener, ObserverCollector, Sink};
+use observer::Observer;
+pub use patch::{Mut, Patch};
+pub use resource::{Res, ResMut, Resource};
+use resource::ResourceCell;
+pub use schedule::{IntoSystemDescriptor, Schedule, Stage, SystemDescriptor};
+pub use storage::{IterationOrder, StorageKind};
+use storage::{SparseSet, StorageCell, TagSet};
+pub use system::{IntoSystem, System, SystemParam, SystemParamFunction};
+pub use tick::{ComponentTicks, Tick};
+pub use view::View;
 
 pub type ComponentId = TypeId;
 
-pub trait ComponentTrait: 'static + Sized {}
+/// A type that can be attached to entities; where it is stored is up to [`Registry::register_storage`].
+pub trait ComponentTrait: 'static + Sized + Send + Sync {}
 
-impl<T: 'static + Sized> ComponentTrait for T {}
-
-trait ComponentStorage {
-    fn remove(&mut self, entity: &Entity);
-    fn is_empty(&self) -> bool;
-    fn len(&self) -> usize;
-    fn as_any(&self) -> &dyn Any;
-    fn as_any_mut(&mut self) -> &mut dyn Any;
-}
-
-impl<T: ComponentTrait> ComponentStorage for HashMap<Entity, T> {
-    fn remove(&mut self, entity: &Entity) { self.remove(entity); }
-    fn is_empty(&self) -> bool { self.is_empty() }
-    fn len(&self) -> usize { self.len() }
-    fn as_any(&self) -> &dyn Any { self }
-    fn as_any_mut(&mut self) -> &mut dyn Any { self }
-}
-
-#[derive(Debug, Default)]
-struct Observer;
+impl<T: 'static + Sized + Send + Sync> ComponentTrait for T {}
 
 pub struct Registry {
-    next: Entity,
+    allocator: EntityAllocator,
     entities: HashMap<Entity, HashSet<ComponentId>>,
-    component_pool: HashMap<ComponentId, Box<dyn ComponentStorage>>,
+    /// The entities that exist, in a set of their own for views requiring no component to walk.
+    live: SparseSet<()>,
+    component_pool: HashMap<ComponentId, StorageCell>,
+    archetypes: Archetypes,
+    default_storage: StorageKind,
+    storage_kinds: HashMap<ComponentId, StorageKind>,
+    iteration_order: IterationOrder,
+    groups: Vec<GroupData>,
+    resources: HashMap<TypeId, ResourceCell>,
     observer: Observer,
+    change_tick: Tick,
+    last_change_tick: Tick,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            allocator: Default::default(),
+            entities: HashMap::new(),
+            live: SparseSet::new(),
+            component_pool: HashMap::new(),
+            archetypes: Default::default(),
+            default_storage: StorageKind::default(),
+            storage_kinds: HashMap::new(),
+            iteration_order: IterationOrder::default(),
+            groups: Vec::new(),
+            resources: HashMap::new(),
+            observer: Default::default(),
+            change_tick: 1,
+            last_change_tick: 0,
+        }
+    }
 }
 
 impl Registry {
     pub fn new() -> Self {
-        Self { next: 1, entities: HashMap::new(), component_pool: HashMap::new(), observer: Default::default() }
+        Self::default()
+    }
+
+    /// A registry storing the components with no registered storage in `storage` rather than in sparse sets, except
+    /// for zero-sized ones, which are tags.
+    pub fn with_default_storage(storage: StorageKind) -> Self {
+        assert!(storage != StorageKind::Tag, "with_default_storage: tag storage only holds zero-sized components");
+        Self { default_storage: storage, ..Self::default() }
+    }
+
+    /// Stores the components of the type in `kind` rather than the default one, see
+    /// [`with_default_storage`](Self::with_default_storage).
+    ///
+    /// Panics if the type already has a storage or is owned by a group, or for a tag storage of a type that is not
+    /// zero-sized.
+    pub fn register_storage<Component: ComponentTrait>(&mut self, kind: StorageKind) -> &mut Self {
+        let (component_id, name) = (TypeId::of::<Component>(), type_name::<Component>());
+        assert!(kind != StorageKind::Tag || size_of::<Component>() == 0, "register_storage: component {} is not zero-sized and cannot be a tag", name);
+        let stored = self.component_pool.contains_key(&component_id) || !self.archetypes.with_component(component_id).is_empty();
+        assert!(!stored, "register_storage: component {} already has a storage", name);
+        assert!(!self.groups.iter().any(|group| group.owned().contains(&component_id)), "register_storage: component {} is owned by a group", name);
+        self.storage_kinds.insert(component_id, kind);
+        self
+    }
+
+    /// A registry keeping its storages in `order`, see [`set_iteration_order`](Self::set_iteration_order).
+    pub fn with_iteration_order(order: IterationOrder) -> Self {
+        Self { iteration_order: order, ..Self::default() }
+    }
+
+    /// Sets the order storages keep their entities in from now on; views visit entities in that order.
+    pub fn set_iteration_order(&mut self, order: IterationOrder) -> &mut Self {
+        self.iteration_order = order;
+        self
+    }
+
+    pub fn iteration_order(&self) -> IterationOrder {
+        self.iteration_order
     }
 
     pub fn create(&mut self) -> Entity {
-        let entity = self.next;
-        self.next += 1;
+        let entity = self.allocator.alloc();
         self.entities.insert(entity, HashSet::new());
+        self.live.insert(entity, (), self.change_tick);
         entity
     }
 
@@ -57,114 +148,527 @@ impl Registry {
         components.create_entity_with(self)
     }
 
+    /// Hands out the id of an entity to be created later by [`create_reserved`](Self::create_reserved);
+    /// it does not exist until then. Safe to call from several threads at once.
+    ///
+    /// An id that ends up not being created must be handed back with [`destroy`](Self::destroy), or its index is never
+    /// reused.
+    pub fn reserve(&self) -> Entity {
+        self.allocator.reserve()
+    }
+
+    pub fn create_reserved(&mut self, entity: Entity) -> Result<(), NecsError> {
+        self.allocator.flush();
+        if self.exists(entity) || !self.allocator.is_allocated(entity) {
+            return Err(NecsError::NotReserved(entity));
+        }
+        self.entities.insert(entity, HashSet::new());
+        self.live.insert(entity, (), self.change_tick);
+        Ok(())
+    }
+
+    /// Applies the commands in the order they were recorded.
+    pub fn apply(&mut self, commands: Commands) {
+        commands.apply(self);
+    }
+
+    pub fn try_destroy(&mut self, entity: Entity) -> Result<(), NecsError> {
+        self.check_entity(entity)?;
+        self.destroy(entity);
+        Ok(())
+    }
+
+    /// Also hands back an id that was reserved but not created.
     pub fn destroy(&mut self, entity: Entity) {
-        if let Some(component_ids) = self.entities.get(&entity) {
-            for component_id in component_ids {
-                let component_storage = self.component_pool.get_mut(component_id).unwrap().as_mut();
-                component_storage.remove(&entity);
-                if component_storage.is_empty() {
-                    self.component_pool.remove(component_id);
-                }
+        // Listeners run between removals and may change the entity, so re-read its components every time. Taking the
+        // smallest id rather than the first in the set keeps the order of destroy events the same from run to run
+        while let Some(component_id) = self.entities.get(&entity).and_then(|component_ids| component_ids.iter().min().copied()) {
+            self.remove_by_id(entity, component_id);
+        }
+        if self.entities.remove(&entity).is_some() {
+            self.live.remove_in_order(entity, self.iteration_order);
+            self.allocator.free(entity);
+        } else {
+            self.allocator.flush();
+            if self.allocator.is_allocated(entity) {
+                self.allocator.free(entity);
             }
         }
-        self.entities.remove(&entity);
     }
 
     pub fn add<Component: ComponentTrait>(&mut self, entity: Entity, new_component: Component) {
         if let Some(component_ids) = self.entities.get_mut(&entity) {
             if component_ids.insert(TypeId::of::<Component>()) {
-                match self.component_pool.entry(TypeId::of::<Component>()) {
-                    Entry::Occupied(mut entry) => {
-                        let map = entry.get_mut().as_any_mut().downcast_mut::<HashMap<Entity, Component>>().unwrap();
-                        map.insert(entity, new_component);
+                match self.storage_kind::<Component>() {
+                    StorageKind::SparseSet => {
+                        let component_storage = self.component_pool.entry(TypeId::of::<Component>())
+                            .or_insert_with(StorageCell::sparse_set::<Component>).get_mut();
+                        component_storage.as_any_mut().downcast_mut::<SparseSet<Component>>().unwrap().insert(entity, new_component, self.change_tick);
                     }
-                    Entry::Vacant(entry) => {
-                        let mut map: HashMap<Entity, Component> = HashMap::new();
-                        map.insert(entity, new_component);
-                        entry.insert(Box::new(map));
+                    StorageKind::Table => {
+                        self.archetypes.insert(entity, new_component, self.change_tick, self.iteration_order);
+                    }
+                    StorageKind::Tag => {
+                        assert!(size_of::<Component>() == 0, "add: component {} is stored as a tag but is not zero-sized", type_name::<Component>());
+                        let component_storage = self.component_pool.entry(TypeId::of::<Component>())
+                            .or_insert_with(StorageCell::tags::<Component>).get_mut();
+                        component_storage.as_any_mut().downcast_mut::<TagSet<Component>>().unwrap().insert(entity, new_component, self.change_tick);
                     }
                 }
+                self.refresh_groups(entity, TypeId::of::<Component>());
+                self.notify(TypeId::of::<Component>(), Event::Construct, entity);
+            }
+        }
+    }
+
+    /// Adds the component, or hands it back if the entity does not exist or already has one.
+    pub fn try_add<Component: ComponentTrait>(&mut self, entity: Entity, new_component: Component) -> Result<(), Rejected<Component>> {
+        let checked = self.check_entity(entity).and_then(|()| {
+            self.check_storage::<Component>()?;
+            if self.has_id(entity, TypeId::of::<Component>()) {
+                return Err(NecsError::ComponentAlreadyPresent { entity, type_name: type_name::<Component>() });
             }
+            Ok(())
+        });
+        match checked {
+            Ok(()) => {
+                self.add(entity, new_component);
+                Ok(())
+            }
+            Err(error) => Err(Rejected { error, component: new_component }),
+        }
+    }
+
+    pub fn emplace_or_replace<Component: ComponentTrait>(&mut self, entity: Entity, new_component: Component) {
+        if self.has_id(entity, TypeId::of::<Component>()) {
+            self.replace(entity, new_component);
+        } else {
+            self.add(entity, new_component);
+        }
+    }
+
+    /// Marks the entity with a default-constructed tag, unless it already has one.
+    pub fn tag<Component: ComponentTrait + Default>(&mut self, entity: Entity) {
+        if !self.has::<Component>(entity) {
+            self.add(entity, Component::default());
         }
     }
 
+    pub fn has<Component: ComponentTrait>(&self, entity: Entity) -> bool {
+        self.has_id(entity, TypeId::of::<Component>())
+    }
+
+    /// The component, added with `func` first if the entity has none, behind a guard that reports writes like
+    /// [`Patch::get_mut`]. Panics if the entity does not exist.
+    pub fn get_or_insert_with<Component: ComponentTrait, F: FnOnce() -> Component>(&mut self, entity: Entity, func: F) -> Mut<'_, Component> {
+        assert!(self.exists(entity), "get_or_insert_with: entity {:?} does not exist", entity);
+        if !self.has_id(entity, TypeId::of::<Component>()) {
+            self.add(entity, func());
+        }
+        assert!(self.has_id(entity, TypeId::of::<Component>()), "get_or_insert_with: component removed by a construct listener");
+        Mut::new(self, entity)
+    }
+
+    pub fn try_remove<Component: ComponentTrait>(&mut self, entity: Entity) -> Result<(), NecsError> {
+        self.check_component::<Component>(entity)?;
+        self.remove::<Component>(entity);
+        Ok(())
+    }
+
     pub fn remove<Component: ComponentTrait>(&mut self, entity: Entity) {
-        if let Some(component_ids) = self.entities.get_mut(&entity) {
-            if component_ids.remove(&TypeId::of::<Component>()) {
-                let component_storage = self.component_pool.get_mut(&TypeId::of::<Component>()).unwrap().as_mut();
-                component_storage.remove(&entity);
+        self.remove_by_id(entity, TypeId::of::<Component>());
+    }
+
+    /// Removes the component from every entity that has it.
+    pub fn clear<Component: ComponentTrait>(&mut self) {
+        let last = |registry: &Self| match registry.storage_kind::<Component>() {
+            StorageKind::SparseSet => registry.storage::<Component>().and_then(|storage| storage.entities().last().copied()),
+            StorageKind::Table => registry.archetypes.any_with(TypeId::of::<Component>()),
+            StorageKind::Tag => registry.tag_storage::<Component>().and_then(|storage| storage.entities().last().copied()),
+        };
+        while let Some(entity) = last(self) {
+            self.remove_by_id(entity, TypeId::of::<Component>());
+        }
+    }
+
+    fn remove_by_id(&mut self, entity: Entity, component_id: ComponentId) {
+        if !self.has_id(entity, component_id) {
+            return;
+        }
+        // Destroy listeners still see the component, as the removal happens only after they return
+        self.notify(component_id, Event::Destroy, entity);
+        if !self.has_id(entity, component_id) {
+            return;
+        }
+        self.unlink_hierarchy(entity, component_id);
+        self.entities.get_mut(&entity).unwrap().remove(&component_id);
+        // Owned components leave their group while still stored, so the removal cannot break its packing
+        self.refresh_groups(entity, component_id);
+        if !self.archetypes.remove(entity, component_id, self.iteration_order) {
+            if let Some(component_pool) = self.component_pool.get_mut(&component_id) {
+                let component_storage = component_pool.get_mut();
+                component_storage.remove(&entity, self.iteration_order);
                 if component_storage.is_empty() {
-                    self.component_pool.remove(&TypeId::of::<Component>());
+                    self.component_pool.remove(&component_id);
                 }
             }
         }
     }
 
+    /// The group of the entities having all of `Owned` and `Get` and none of `Exclude`, created on first use and kept
+    /// up to date as components are added and removed.
+    ///
+    /// An owning group packs its entities at the front of the sparse sets of `Owned`, so walking it is a linear scan
+    /// of those storages side by side. With no `Owned` components, the group keeps a set of its entities instead,
+    /// which [`view_all`](Self::view_all) over exactly `Get` walks rather than the smallest storage.
+    ///
+    /// Panics if an owned component is stored in tables, is owned by another group, or if the sets overlap.
+    pub fn group<Owned, Get, Exclude>(&mut self) -> Group<'_>
+        where Owned: ComponentTuple<'static>, Get: ComponentTuple<'static>, Exclude: ComponentTuple<'static>
+    {
+        let (owned, get, exclude) = (Owned::component_ids(), Get::component_ids(), Exclude::component_ids());
+        if let Some(group) = self.groups.iter().position(|group| group.is(&owned, &get, &exclude)) {
+            return Group::new(self, group);
+        }
+        assert!(!owned.is_empty() || !get.is_empty(), "group: a group must own or get at least one component");
+        for ((id, name), kind) in owned.iter().zip(Owned::type_names()).zip(Owned::storage_kinds(self)) {
+            assert!(kind != StorageKind::Table, "group: component {} is stored in tables and cannot be owned", name);
+            assert!(!self.groups.iter().any(|group| group.owned().contains(id)), "group: component {} is already owned by another group", name);
+            assert!(!get.contains(id) && !exclude.contains(id), "group: component {} is both owned and got or excluded", name);
+        }
+        assert!(get.iter().all(|id| !exclude.contains(id)), "group: components are both got and excluded");
+        let type_names = [Owned::type_names(), Get::type_names(), Exclude::type_names()].concat();
+        let mut group = GroupData::new(owned, get, exclude, type_names);
+        let mut candidates: Vec<Entity> = match group.owned().first() {
+            Some(id) => self.component_pool.get(id).map_or_else(Vec::new, |cell| cell.get().entities().to_vec()),
+            None => self.entities.keys().copied().collect(),
+        };
+        if group.owned().is_empty() {
+            candidates.sort();
+        }
+        for entity in candidates {
+            group.refresh(entity, &self.entities[&entity], &mut self.component_pool, self.iteration_order);
+        }
+        self.groups.push(group);
+        Group::new(self, self.groups.len() - 1)
+    }
+
+    /// Reorders the sparse set of the component by value, so that views walking it follow that order.
+    /// The entities of an owning group stay packed, in the sorted order, at the front of the owned storages, and
+    /// groups owning nothing whose first got component this is are walked in the sorted order too.
+    pub fn sort<Component: ComponentTrait>(&mut self, mut compare: impl FnMut(&Component, &Component) -> Ordering) {
+        assert!(self.storage_kind::<Component>() == StorageKind::SparseSet, "sort: component {} is not stored in a sparse set", type_name::<Component>());
+        let Some(storage) = self.storage::<Component>() else { return };
+        let mut order: Vec<usize> = (0..storage.len()).collect();
+        order.sort_by(|a, b| compare(&storage.values()[*a], &storage.values()[*b]));
+        self.reorder(TypeId::of::<Component>(), order);
+    }
+
+    /// Reorders the storage of `Follower` so that the entities it shares with `Leader` come first, in the order of
+    /// `Leader`, followed by the others in their current order.
+    pub fn sort_as<Leader: ComponentTrait, Follower: ComponentTrait>(&mut self) {
+        for (kind, name) in [(self.storage_kind::<Leader>(), type_name::<Leader>()), (self.storage_kind::<Follower>(), type_name::<Follower>())] {
+            assert!(kind != StorageKind::Table, "sort_as: component {} is stored in tables", name);
+        }
+        let pool = |id: ComponentId| self.component_pool.get(&id).map(StorageCell::get);
+        let (Some(leader), Some(follower)) = (pool(TypeId::of::<Leader>()), pool(TypeId::of::<Follower>())) else { return };
+        let mut order: Vec<usize> = leader.entities().iter().filter_map(|entity| follower.index_of(entity)).collect();
+        order.extend((0..follower.len()).filter(|index| !leader.contains(&follower.entities()[*index])));
+        self.reorder(TypeId::of::<Follower>(), order);
+    }
+
+    /// Moves the entry at `order[i]` of the storage to `i`, keeping the entities of its owning group, if any, packed
+    /// at the front of every owned storage, and the groups owning nothing that get it first in the same order.
+    fn reorder(&mut self, component_id: ComponentId, mut order: Vec<usize>) {
+        let group = self.groups.iter().find(|group| group.owned().contains(&component_id));
+        let (owned, len) = group.map_or((vec![component_id], 0), |group| (group.owned().to_vec(), group.len()));
+        order.sort_by_key(|index| *index >= len);
+        for id in owned {
+            let order = if id == component_id { &order[..] } else { &order[..len] };
+            // Owned components no entity has yet have no storage, and then the group is empty
+            if let Some(component_pool) = self.component_pool.get_mut(&id) {
+                component_pool.get_mut().permute(order);
+            }
+        }
+        if let Some(component_pool) = self.component_pool.get(&component_id) {
+            for group in &mut self.groups {
+                group.follow(component_id, component_pool.get().entities());
+            }
+        }
+    }
+
+    /// Lists the groups created so far, in creation order.
+    pub fn groups(&self) -> impl Iterator<Item = GroupInfo> + '_ {
+        self.groups.iter().map(GroupData::info)
+    }
+
+    fn refresh_groups(&mut self, entity: Entity, component_id: ComponentId) {
+        let Some(component_ids) = self.entities.get(&entity) else { return };
+        for group in self.groups.iter_mut().filter(|group| group.involves(component_id)) {
+            group.refresh(entity, component_ids, &mut self.component_pool, self.iteration_order);
+        }
+    }
+
+    pub fn try_replace<Component: ComponentTrait>(&mut self, entity: Entity, new_component: Component) -> Result<(), NecsError> {
+        self.try_patch::<Component>(entity)?.with(move |component| *component = new_component);
+        Ok(())
+    }
+
     pub fn replace<Component: ComponentTrait>(&mut self, entity: Entity, new_component: Component) {
         self.patch::<Component>(entity).with(move |component| *component = new_component);
     }
 
-    pub fn patch<Component: ComponentTrait>(&mut self, entity: Entity) -> Patch<Component> {
-        let component = self.component_pool.get_mut(&TypeId::of::<Component>()).and_then(|component_pool| {
-            let component_storage = component_pool.as_any_mut().downcast_mut::<HashMap<Entity, Component>>().unwrap();
-            component_storage.get_mut(&entity)
-        });
-        // TODO: notify observer here, instead of passing to Patch, why? that will allow multiple mut Patches
-        Patch { observer: &mut self.observer, component }
+    pub fn try_patch<Component: ComponentTrait>(&mut self, entity: Entity) -> Result<Patch<'_, Component>, NecsError> {
+        self.check_component::<Component>(entity)?;
+        Ok(self.patch(entity))
+    }
+
+    pub fn patch<Component: ComponentTrait>(&mut self, entity: Entity) -> Patch<'_, Component> {
+        Patch::new(self, entity)
+    }
+
+    pub fn try_get<Component: ComponentTrait>(&self, entity: Entity) -> Result<&Component, NecsError> {
+        self.check_component::<Component>(entity)?;
+        self.get(entity).ok_or(NecsError::ComponentMissing { entity, type_name: type_name::<Component>() })
     }
 
     pub fn get<Component: ComponentTrait>(&self, entity: Entity) -> Option<&Component> {
-        self.component_pool.get(&TypeId::of::<Component>()).and_then(|component_pool| {
-            let component_storage = component_pool.as_any().downcast_ref::<HashMap<Entity, Component>>().unwrap();
-            component_storage.get(&entity)
-        })
+        match self.storage_kind::<Component>() {
+            StorageKind::SparseSet => self.storage::<Component>().and_then(|component_storage| component_storage.get(entity)),
+            StorageKind::Table => self.archetypes.component(entity),
+            StorageKind::Tag => self.tag_storage::<Component>().and_then(|component_storage| component_storage.get(entity)),
+        }
     }
 
     pub fn get_all<'r, Components: ComponentTuple<'r>>(&'r self, entity: Entity) -> Components::AsOption {
         Components::get_components(entity, self)
     }
 
-    pub fn view_all<'r, Components: ComponentTuple<'r>>(&'r self) -> Vec<(Entity, Components::AsRef)> {
-        Components::view_entities(self)
+    /// Views the entities having all the components, walking the group of exactly those components if there is one.
+    /// Filters are not components: queries filtering with [`With`], [`Without`], [`Added`] or [`Changed`] go through
+    /// [`view`](Self::view) and [`view_mut`](Self::view_mut).
+    pub fn view_all<'r, Components: ComponentTuple<'r>>(&'r self) -> View<'r, Components::AsRef> {
+        let component_ids = Components::component_ids();
+        match self.groups.iter().find(|group| group.caches(&component_ids)) {
+            // SAFETY: nothing is fetched mutably
+            Some(group) => unsafe { View::with_candidates(self, self.last_change_tick, Candidates::Entities(group.entities(&self.component_pool))) },
+            None => Components::view_entities(self),
+        }
+    }
+
+    /// Views the entities matching the query, a tuple of component references and filters.
+    pub fn view<Query: ReadOnlyFetch>(&self) -> View<'_, Query> {
+        View::new(self)
+    }
+
+    pub fn view_mut<Query: Fetch>(&mut self) -> View<'_, Query> {
+        if let Some(component) = Access::of::<Query>().conflicts().first() {
+            panic!("view_mut: component {} is borrowed mutably more than once", component);
+        }
+        // SAFETY: the registry is borrowed exclusively and the query never aliases a mutably fetched component
+        unsafe { View::new_unchecked(self, self.last_change_tick) }
+    }
+
+    pub fn ticks<Component: ComponentTrait>(&self, entity: Entity) -> Option<ComponentTicks> {
+        match self.storage_kind::<Component>() {
+            StorageKind::SparseSet => self.storage::<Component>().and_then(|component_storage| component_storage.ticks(entity)),
+            StorageKind::Table => self.archetypes.ticks::<Component>(entity),
+            StorageKind::Tag => self.tag_storage::<Component>().and_then(|component_storage| component_storage.ticks(entity)),
+        }
+    }
+
+    /// The archetype tables of the components stored in [`StorageKind::Table`], the first of which is always empty.
+    pub fn archetypes(&self) -> std::slice::Iter<'_, Archetype> {
+        self.archetypes.iter()
+    }
+
+    pub fn storage_kind<Component: ComponentTrait>(&self) -> StorageKind {
+        match self.storage_kinds.get(&TypeId::of::<Component>()) {
+            Some(kind) => *kind,
+            None if size_of::<Component>() == 0 => StorageKind::Tag,
+            None => self.default_storage,
+        }
+    }
+
+    /// The tick stamped on components added or changed from now on.
+    pub fn change_tick(&self) -> Tick {
+        self.change_tick
+    }
+
+    /// The tick `Added` and `Changed` filters compare against: changes stamped after it are reported.
+    pub fn last_change_tick(&self) -> Tick {
+        self.last_change_tick
+    }
+
+    /// Marks the end of a system run, so the changes made so far are no longer reported by `Added` and `Changed`.
+    pub fn advance_tick(&mut self) {
+        self.last_change_tick = self.change_tick;
+        self.change_tick += 1;
+    }
+
+    /// Inserts the resource `R`, returning the previous value, which counts as a change.
+    pub fn insert_resource<R: Resource>(&mut self, value: R) -> Option<R> {
+        let tick = self.change_tick;
+        match self.resources.get_mut(&TypeId::of::<R>()) {
+            Some(resource) => {
+                let (resource, ticks) = resource.value_mut::<R>();
+                ticks.changed = tick;
+                Some(std::mem::replace(resource, value))
+            }
+            None => {
+                self.resources.insert(TypeId::of::<R>(), ResourceCell::resource(value, tick));
+                None
+            }
+        }
+    }
+
+    pub fn remove_resource<R: Resource>(&mut self) -> Option<R> {
+        self.resources.remove(&TypeId::of::<R>()).map(ResourceCell::into_value)
+    }
+
+    pub fn contains_resource<R: Resource>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<R>())
+    }
+
+    pub fn resource<R: Resource>(&self) -> Option<&R> {
+        self.resources.get(&TypeId::of::<R>()).map(ResourceCell::value)
+    }
+
+    pub fn resource_mut<R: Resource>(&mut self) -> Option<ResMut<'_, R>> {
+        let (last_run, tick) = (self.last_change_tick, self.change_tick);
+        self.resources.get_mut(&TypeId::of::<R>()).map(|resource| {
+            let (value, ticks) = resource.value_mut();
+            ResMut::new(value, ticks, last_run, tick)
+        })
+    }
+
+    pub fn resource_ticks<R: Resource>(&self) -> Option<ComponentTicks> {
+        self.resources.get(&TypeId::of::<R>()).map(ResourceCell::ticks::<R>)
     }
 
     pub fn exists(&self, entity: Entity) -> bool {
         self.entities.contains_key(&entity)
     }
 
-    // TODO: add_or_replace(component)
-    // TODO: clear<component>()
-}
+    fn check_entity(&self, entity: Entity) -> Result<(), NecsError> {
+        if self.exists(entity) { Ok(()) } else { Err(NecsError::NoSuchEntity(entity)) }
+    }
 
-pub struct Patch<'r, Component> {
-    observer: &'r mut Observer,
-    component: Option<&'r mut Component>,
-}
+    fn check_component<Component: ComponentTrait>(&self, entity: Entity) -> Result<(), NecsError> {
+        self.check_entity(entity)?;
+        self.check_storage::<Component>()?;
+        if self.has_id(entity, TypeId::of::<Component>()) {
+            Ok(())
+        } else {
+            Err(NecsError::ComponentMissing { entity, type_name: type_name::<Component>() })
+        }
+    }
+
+    fn has_id(&self, entity: Entity, component_id: ComponentId) -> bool {
+        self.entities.get(&entity).is_some_and(|component_ids| component_ids.contains(&component_id))
+    }
+
+    pub fn on_construct<Component: ComponentTrait>(&mut self) -> Sink<'_> {
+        Sink::new(&mut self.observer, TypeId::of::<Component>(), Event::Construct)
+    }
+
+    pub fn on_update<Component: ComponentTrait>(&mut self) -> Sink<'_> {
+        Sink::new(&mut self.observer, TypeId::of::<Component>(), Event::Update)
+    }
+
+    pub fn on_destroy<Component: ComponentTrait>(&mut self) -> Sink<'_> {
+        Sink::new(&mut self.observer, TypeId::of::<Component>(), Event::Destroy)
+    }
+
+    pub fn collector<Component: ComponentTrait>(&mut self) -> CollectorBuilder<'_, Component> {
+        CollectorBuilder::new(self)
+    }
+
+    pub fn disconnect(&mut self, connection: Connection) -> bool {
+        self.observer.disconnect(connection)
+    }
 
-impl<'r, Component> Patch<'r, Component> {
-    pub fn with<F: FnOnce(&mut Component)>(&mut self, func: F) {
-        if let Some(component) = &mut self.component {
-            func(component);
-            // TODO: Notify registry observer/event-manager
+    fn notify(&mut self, component_id: ComponentId, event: Event, entity: Entity) {
+        if let Some(listeners) = self.observer.listeners(component_id, event) {
+            for listener in listeners {
+                listener(self, entity);
+            }
         }
     }
-    // TODO: fn get_mut() ? should also notify the observer
+
+    /// Checks that the pool of the component, if any, holds the storage its kind calls for.
+    fn check_storage<Component: ComponentTrait>(&self) -> Result<(), NecsError> {
+        let Some(component_pool) = self.component_pool.get(&TypeId::of::<Component>()) else { return Ok(()) };
+        let storage = component_pool.get().as_any();
+        let expected = match self.storage_kind::<Component>() {
+            StorageKind::SparseSet => storage.is::<SparseSet<Component>>(),
+            StorageKind::Tag => storage.is::<TagSet<Component>>(),
+            StorageKind::Table => false,
+        };
+        if expected { Ok(()) } else { Err(NecsError::StorageTypeMismatch { type_name: type_name::<Component>() }) }
+    }
+
+    fn storage<Component: ComponentTrait>(&self) -> Option<&SparseSet<Component>> {
+        self.component_pool.get(&TypeId::of::<Component>())
+            .map(|component_pool| component_pool.get().as_any().downcast_ref::<SparseSet<Component>>().unwrap())
+    }
+
+    /// Stamps the component as changed.
+    fn get_mut_changed<Component: ComponentTrait>(&mut self, entity: Entity) -> Option<&mut Component> {
+        let tick = self.change_tick;
+        match self.storage_kind::<Component>() {
+            StorageKind::SparseSet => self.storage_mut::<Component>().and_then(|storage| storage.get_mut_changed(entity, tick)),
+            StorageKind::Table => self.archetypes.get_mut_changed(entity, tick),
+            StorageKind::Tag => self.tag_storage_mut::<Component>().and_then(|storage| storage.get_mut_changed(entity, tick)),
+        }
+    }
+
+    fn tag_storage<Component: ComponentTrait>(&self) -> Option<&TagSet<Component>> {
+        self.component_pool.get(&TypeId::of::<Component>())
+            .map(|component_pool| component_pool.get().as_any().downcast_ref::<TagSet<Component>>().unwrap())
+    }
+
+    fn tag_storage_mut<Component: ComponentTrait>(&mut self) -> Option<&mut TagSet<Component>> {
+        self.component_pool.get_mut(&TypeId::of::<Component>())
+            .map(|component_pool| component_pool.get_mut().as_any_mut().downcast_mut::<TagSet<Component>>().unwrap())
+    }
+
+    fn storage_mut<Component: ComponentTrait>(&mut self) -> Option<&mut SparseSet<Component>> {
+        self.component_pool.get_mut(&TypeId::of::<Component>())
+            .map(|component_pool| component_pool.get_mut().as_any_mut().downcast_mut::<SparseSet<Component>>().unwrap())
+    }
+
+    fn resource_ptr<R: Resource>(&self) -> Option<(std::ptr::NonNull<R>, std::ptr::NonNull<ComponentTicks>)> {
+        self.resources.get(&TypeId::of::<R>()).map(ResourceCell::value_ptr)
+    }
 }
 
 pub trait ComponentTuple<'r> {
     type AsOption;
-    type AsRef;
+    type AsRef: ReadOnlyFetch;
     fn create_entity_with(self, registry: &mut Registry) -> Entity;
+    fn add_components(self, entity: Entity, registry: &mut Registry);
     fn get_components(entity: Entity, registry: &'r Registry) -> Self::AsOption;
-    fn view_entities(_registry: &'r Registry) -> Vec<(Entity, Self::AsRef)>;
+    fn view_entities(registry: &'r Registry) -> View<'r, Self::AsRef>;
+    fn component_ids() -> Vec<ComponentId>;
+    fn type_names() -> Vec<&'static str>;
+    fn storage_kinds(registry: &Registry) -> Vec<StorageKind>;
 }
 
-// Reference: https://doc.rust-lang.org/1.5.0/src/core/tuple.rs.html#39-57
-// FIXME(#19630) Remove this work-around
-macro_rules! expr {
-    ($e:expr) => { $e }
+impl<'r> ComponentTuple<'r> for () {
+    type AsOption = ();
+    type AsRef = ();
+
+    fn create_entity_with(self, registry: &mut Registry) -> Entity { registry.create() }
+    fn add_components(self, _entity: Entity, _registry: &mut Registry) {}
+    fn get_components(_entity: Entity, _registry: &'r Registry) -> Self::AsOption {}
+    fn view_entities(registry: &'r Registry) -> View<'r, Self::AsRef> { View::new(registry) }
+    fn component_ids() -> Vec<ComponentId> { Vec::new() }
+    fn type_names() -> Vec<&'static str> { Vec::new() }
+    fn storage_kinds(_registry: &Registry) -> Vec<StorageKind> { Vec::new() }
 }
 
+// Reference: https://doc.rust-lang.org/1.5.0/src/core/tuple.rs.html#39-57
 macro_rules! impl_component_tuple {
     ( $( $T:ident.$idx:tt ),+ ) => {
         impl<'r, $( $T ),+> ComponentTuple<'r> for ( $( $T, )+ )
@@ -175,10 +679,17 @@ macro_rules! impl_component_tuple {
 
             fn create_entity_with(self, registry: &mut Registry) -> Entity {
                 let entity = registry.create();
+                self.add_components(entity, registry);
+                entity
+            }
+
+            #[allow(non_snake_case)]
+            fn add_components(self, entity: Entity, registry: &mut Registry) {
+                // Destructure rather than index, so components are added (and observed) in tuple order
+                let ( $( $T, )+ ) = self;
                 $(
-                    registry.add(entity, expr!(self.$idx));
+                    registry.add(entity, $T);
                 )+
-                entity
             }
 
             fn get_components(entity: Entity, registry: &'r Registry) -> Self::AsOption {
@@ -189,30 +700,20 @@ macro_rules! impl_component_tuple {
                 )
             }
 
-            fn view_entities(registry: &'r Registry) -> Vec<(Entity, Self::AsRef)> {
-                let storages = (
-                    $(
-                        registry.component_pool.get(&TypeId::of::<$T>()).and_then(|component_pool| {
-                            component_pool.as_any().downcast_ref::<HashMap<Entity, $T>>()
-                        }),
-                    )+
-                );
-                let storage_noexist = $( expr!(storages.$idx).is_none() )||+;
-                if storage_noexist {
-                    return Default::default();
-                }
-                let storages = ( $( expr!(storages.$idx).unwrap(), )+ );
-                let storages = ( $( expr!(storages.$idx), )+ );
-                let mut vec = Vec::new();
-                for entity in storages.0.keys() {
-                    let components = ( $( expr!(storages.$idx).get(&entity), )+ );
-                    let exist = $( expr!(components.$idx).is_some() )&&+;
-                    if exist {
-                        let components = ( $( expr!(components.$idx).unwrap(), )+ );
-                        vec.push((*entity, components));
-                    }
-                }
-                vec
+            fn view_entities(registry: &'r Registry) -> View<'r, Self::AsRef> {
+                View::new(registry)
+            }
+
+            fn component_ids() -> Vec<ComponentId> {
+                vec![ $( TypeId::of::<$T>(), )+ ]
+            }
+
+            fn type_names() -> Vec<&'static str> {
+                vec![ $( type_name::<$T>(), )+ ]
+            }
+
+            fn storage_kinds(registry: &Registry) -> Vec<StorageKind> {
+                vec![ $( registry.storage_kind::<$T>(), )+ ]
             }
         }
     }
@@ -0,0 +1,199 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use crate::{ComponentId, ComponentTrait, ComponentTuple, Entity, Registry, SparseSet};
+
+pub type Listener = Arc<dyn Fn(&mut Registry, Entity) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Event {
+    Construct,
+    Update,
+    Destroy,
+}
+
+/// Handle to a connected listener, used to disconnect it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Connection {
+    component_id: ComponentId,
+    event: Event,
+    id: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct Observer {
+    next_id: u64,
+    listeners: HashMap<(ComponentId, Event), Vec<(u64, Listener)>>,
+}
+
+impl Observer {
+    pub fn connect(&mut self, component_id: ComponentId, event: Event, listener: Listener) -> Connection {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.listeners.entry((component_id, event)).or_default().push((id, listener));
+        Connection { component_id, event, id }
+    }
+
+    pub fn disconnect(&mut self, connection: Connection) -> bool {
+        let key = (connection.component_id, connection.event);
+        let Some(listeners) = self.listeners.get_mut(&key) else { return false };
+        let len = listeners.len();
+        listeners.retain(|(id, _)| *id != connection.id);
+        let disconnected = listeners.len() != len;
+        if listeners.is_empty() {
+            self.listeners.remove(&key);
+        }
+        disconnected
+    }
+
+    pub fn len(&self, component_id: ComponentId, event: Event) -> usize {
+        self.listeners.get(&(component_id, event)).map_or(0, Vec::len)
+    }
+
+    /// Snapshot of the listeners, so they can be called with the registry borrowed mutably.
+    pub fn listeners(&self, component_id: ComponentId, event: Event) -> Option<Vec<Listener>> {
+        let listeners = self.listeners.get(&(component_id, event))?;
+        Some(listeners.iter().map(|(_, listener)| listener.clone()).collect())
+    }
+}
+
+/// Connection point for the listeners of one event of one component type.
+pub struct Sink<'r> {
+    observer: &'r mut Observer,
+    component_id: ComponentId,
+    event: Event,
+}
+
+impl<'r> Sink<'r> {
+    pub(crate) fn new(observer: &'r mut Observer, component_id: ComponentId, event: Event) -> Self {
+        Self { observer, component_id, event }
+    }
+
+    pub fn connect<F: Fn(&mut Registry, Entity) + Send + Sync + 'static>(&mut self, listener: F) -> Connection {
+        self.observer.connect(self.component_id, self.event, Arc::new(listener))
+    }
+
+    pub fn disconnect(&mut self, connection: Connection) -> bool {
+        self.observer.disconnect(connection)
+    }
+
+    pub fn len(&self) -> usize {
+        self.observer.len(self.component_id, self.event)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Builds an [`ObserverCollector`] for the component `T`.
+pub struct CollectorBuilder<'r, T> {
+    registry: &'r mut Registry,
+    require: Vec<ComponentId>,
+    exclude: Vec<ComponentId>,
+    _marker: PhantomData<T>,
+}
+
+impl<'r, T: ComponentTrait> CollectorBuilder<'r, T> {
+    pub(crate) fn new(registry: &'r mut Registry) -> Self {
+        Self { registry, require: Vec::new(), exclude: Vec::new(), _marker: PhantomData }
+    }
+
+    /// Only collect entities that also have all of `Components` at the time `T` changes.
+    pub fn where_<Components: ComponentTuple<'static>>(mut self) -> Self {
+        self.require.extend(Components::component_ids());
+        self
+    }
+
+    /// Only collect entities that have none of `Components` at the time `T` changes.
+    pub fn exclude<Components: ComponentTuple<'static>>(mut self) -> Self {
+        self.exclude.extend(Components::component_ids());
+        self
+    }
+
+    pub fn connect(self) -> ObserverCollector {
+        let entities = Arc::new(Mutex::new(SparseSet::new()));
+        let connections = Arc::new(OnceLock::new());
+        let (require, exclude) = (self.require, self.exclude);
+        let collect = listener(&entities, &connections, move |entities, registry, entity| {
+            let matches = require.iter().all(|component_id| registry.has_id(entity, *component_id))
+                && !exclude.iter().any(|component_id| registry.has_id(entity, *component_id));
+            if matches {
+                entities.insert(entity, (), 0);
+            }
+        });
+        let forget = listener(&entities, &connections, |entities, _, entity| {
+            entities.remove(entity);
+        });
+        let observer = &mut self.registry.observer;
+        let connected = vec![
+            observer.connect(TypeId::of::<T>(), Event::Construct, collect.clone()),
+            observer.connect(TypeId::of::<T>(), Event::Update, collect),
+            observer.connect(TypeId::of::<T>(), Event::Destroy, forget),
+        ];
+        connections.get_or_init(|| connected.clone());
+        ObserverCollector { entities, connections: connected }
+    }
+}
+
+/// A listener of a collector, calling `func` on its entities as long as the collector lives. Once it is dropped, the
+/// first listener to run disconnects all of them.
+fn listener<F>(entities: &Arc<Mutex<SparseSet<()>>>, connections: &Arc<OnceLock<Vec<Connection>>>, func: F) -> Listener
+    where F: Fn(&mut SparseSet<()>, &Registry, Entity) + Send + Sync + 'static
+{
+    let (entities, connections) = (Arc::downgrade(entities), connections.clone());
+    Arc::new(move |registry: &mut Registry, entity: Entity| match entities.upgrade() {
+        Some(entities) => func(&mut entities.lock().unwrap(), registry, entity),
+        None => {
+            for connection in connections.get().into_iter().flatten() {
+                registry.disconnect(*connection);
+            }
+        }
+    })
+}
+
+/// Reactive set of the entities whose observed component was added or patched since the last [`clear`](Self::clear).
+///
+/// Dropping the collector without [`disconnect`](Self::disconnect)ing it leaves its listeners connected until the next
+/// event of the component, which disconnects them.
+pub struct ObserverCollector {
+    entities: Arc<Mutex<SparseSet<()>>>,
+    connections: Vec<Connection>,
+}
+
+impl ObserverCollector {
+    fn entities(&self) -> MutexGuard<'_, SparseSet<()>> {
+        self.entities.lock().unwrap()
+    }
+
+    pub fn len(&self) -> usize { self.entities().len() }
+    pub fn is_empty(&self) -> bool { self.entities().is_empty() }
+    pub fn contains(&self, entity: Entity) -> bool { self.entities().contains(entity) }
+
+    /// Iterates a snapshot of the collected entities.
+    pub fn iter(&self) -> std::vec::IntoIter<Entity> {
+        self.entities().entities().to_vec().into_iter()
+    }
+
+    pub fn clear(&self) {
+        *self.entities() = SparseSet::new();
+    }
+
+    /// Stops collecting; the entities collected so far are kept.
+    pub fn disconnect(&mut self, registry: &mut Registry) {
+        for connection in self.connections.drain(..) {
+            registry.disconnect(connection);
+        }
+    }
+}
+
+impl IntoIterator for &ObserverCollector {
+    type Item = Entity;
+    type IntoIter = std::vec::IntoIter<Entity>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,91 @@
+use std::any::{type_name, TypeId};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use crate::{hierarchy, ComponentTrait, Entity, Event, Registry};
+
+pub struct Patch<'r, Component> {
+    registry: &'r mut Registry,
+    entity: Entity,
+    _marker: PhantomData<Component>,
+}
+
+impl<'r, Component: ComponentTrait> Patch<'r, Component> {
+    pub(crate) fn new(registry: &'r mut Registry, entity: Entity) -> Self {
+        assert!(!hierarchy::is_link(TypeId::of::<Component>()), "patch: component {} is maintained by the registry", type_name::<Component>());
+        Self { registry, entity, _marker: PhantomData }
+    }
+
+    /// Returns whether the entity had the component, i.e. whether `func` was called.
+    pub fn with<F: FnOnce(&mut Component)>(&mut self, func: F) -> bool {
+        let entity = self.entity;
+        if let Some(component) = self.registry.get_mut_changed::<Component>(entity) {
+            func(component);
+            self.registry.notify(TypeId::of::<Component>(), Event::Update, entity);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_some(&self) -> bool {
+        self.get().is_some()
+    }
+
+    pub fn get(&self) -> Option<&Component> {
+        self.registry.get::<Component>(self.entity)
+    }
+
+    pub fn get_mut(&mut self) -> Option<Mut<'_, Component>> {
+        if self.is_some() {
+            Some(Mut::new(self.registry, self.entity))
+        } else {
+            None
+        }
+    }
+}
+
+/// Mutable access to a component that stamps it as changed when mutably dereferenced,
+/// and notifies the update listeners once dropped.
+pub struct Mut<'p, Component: ComponentTrait> {
+    registry: &'p mut Registry,
+    entity: Entity,
+    changed: bool,
+    _marker: PhantomData<Component>,
+}
+
+impl<'p, Component: ComponentTrait> Mut<'p, Component> {
+    /// The entity must have the component.
+    pub(crate) fn new(registry: &'p mut Registry, entity: Entity) -> Self {
+        assert!(!hierarchy::is_link(TypeId::of::<Component>()), "patch: component {} is maintained by the registry", type_name::<Component>());
+        Self { registry, entity, changed: false, _marker: PhantomData }
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+}
+
+impl<Component: ComponentTrait> Deref for Mut<'_, Component> {
+    type Target = Component;
+
+    fn deref(&self) -> &Component {
+        // The guard borrows the registry exclusively, so the component cannot go away while it lives
+        self.registry.get::<Component>(self.entity).unwrap()
+    }
+}
+
+impl<Component: ComponentTrait> DerefMut for Mut<'_, Component> {
+    fn deref_mut(&mut self) -> &mut Component {
+        self.changed = true;
+        self.registry.get_mut_changed::<Component>(self.entity).unwrap()
+    }
+}
+
+impl<Component: ComponentTrait> Drop for Mut<'_, Component> {
+    fn drop(&mut self) {
+        if self.changed {
+            self.registry.notify(TypeId::of::<Component>(), Event::Update, self.entity);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::any::{type_name, Any};
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+use crate::cell::SharedCell;
+use crate::{ComponentTicks, Tick};
+
+pub trait Resource: 'static + Send + Sync {}
+
+impl<T: 'static + Send + Sync> Resource for T {}
+
+/// A resource value along with its ticks, boxed as an `(R, ComponentTicks)`; see [`SharedCell`].
+pub(crate) type ResourceCell = SharedCell<dyn Any + Send + Sync>;
+
+impl ResourceCell {
+    pub fn resource<R: Resource>(value: R, tick: Tick) -> Self {
+        SharedCell::new(Box::new((value, ComponentTicks::new(tick))))
+    }
+
+    fn entry<R: Resource>(&self) -> &(R, ComponentTicks) {
+        self.get().downcast_ref().unwrap()
+    }
+
+    pub fn value<R: Resource>(&self) -> &R { &self.entry::<R>().0 }
+    pub fn ticks<R: Resource>(&self) -> ComponentTicks { self.entry::<R>().1 }
+
+    pub fn value_mut<R: Resource>(&mut self) -> (&mut R, &mut ComponentTicks) {
+        let (value, ticks) = self.get_mut().downcast_mut().unwrap();
+        (value, ticks)
+    }
+
+    pub fn into_value<R: Resource>(self) -> R {
+        self.into_inner().downcast::<(R, ComponentTicks)>().ok().unwrap().0
+    }
+
+    /// Pointers to the value and its ticks; see [`SharedCell::as_ptr`].
+    pub fn value_ptr<R: Resource>(&self) -> (NonNull<R>, NonNull<ComponentTicks>) {
+        assert!(self.get().is::<(R, ComponentTicks)>(), "value_ptr: resource is not a {}", type_name::<R>());
+        let entry = self.as_ptr().cast::<(R, ComponentTicks)>().as_ptr();
+        // SAFETY: the fields of a live value are never null, and no reference is created on the way
+        unsafe { (NonNull::new_unchecked(&raw mut (*entry).0), NonNull::new_unchecked(&raw mut (*entry).1)) }
+    }
+}
+
+/// Shared access to the resource `R`.
+pub struct Res<'r, R: Resource> {
+    value: &'r R,
+    ticks: ComponentTicks,
+    last_run: Tick,
+}
+
+impl<'r, R: Resource> Res<'r, R> {
+    pub(crate) fn new(value: &'r R, ticks: ComponentTicks, last_run: Tick) -> Self {
+        Self { value, ticks, last_run }
+    }
+
+    pub fn is_added(&self) -> bool { self.ticks.is_added(self.last_run) }
+    pub fn is_changed(&self) -> bool { self.ticks.is_changed(self.last_run) }
+    pub fn into_inner(self) -> &'r R { self.value }
+}
+
+impl<R: Resource> Deref for Res<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.value
+    }
+}
+
+/// Exclusive access to the resource `R`, stamping it as changed when dereferenced mutably.
+pub struct ResMut<'r, R: Resource> {
+    value: &'r mut R,
+    ticks: &'r mut ComponentTicks,
+    last_run: Tick,
+    tick: Tick,
+}
+
+impl<'r, R: Resource> ResMut<'r, R> {
+    pub(crate) fn new(value: &'r mut R, ticks: &'r mut ComponentTicks, last_run: Tick, tick: Tick) -> Self {
+        Self { value, ticks, last_run, tick }
+    }
+
+    pub fn is_added(&self) -> bool { self.ticks.is_added(self.last_run) }
+    pub fn is_changed(&self) -> bool { self.ticks.is_changed(self.last_run) }
+}
+
+impl<R: Resource> Deref for ResMut<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.value
+    }
+}
+
+impl<R: Resource> DerefMut for ResMut<'_, R> {
+    fn deref_mut(&mut self) -> &mut R {
+        self.ticks.changed = self.tick;
+        self.value
+    }
+}
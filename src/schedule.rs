@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+
+use crate::executor::ThreadPool;
+use crate::{Executor, IntoSystem, NecsError, Registry, System};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    /// Runs once, before the first update.
+    Startup,
+    PreUpdate,
+    Update,
+    PostUpdate,
+}
+
+pub struct SystemDescriptor {
+    system: Box<dyn System>,
+    labels: Vec<&'static str>,
+    before: Vec<&'static str>,
+    after: Vec<&'static str>,
+}
+
+impl SystemDescriptor {
+    pub fn label(mut self, label: &'static str) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    /// Runs the system before the systems labelled `label` in the same stage.
+    pub fn before(mut self, label: &'static str) -> Self {
+        self.before.push(label);
+        self
+    }
+
+    /// Runs the system after the systems labelled `label` in the same stage.
+    pub fn after(mut self, label: &'static str) -> Self {
+        self.after.push(label);
+        self
+    }
+}
+
+pub struct IsSystemDescriptor;
+
+pub trait IntoSystemDescriptor<Marker>: Sized {
+    fn into_descriptor(self) -> SystemDescriptor;
+
+    fn label(self, label: &'static str) -> SystemDescriptor { self.into_descriptor().label(label) }
+    fn before(self, label: &'static str) -> SystemDescriptor { self.into_descriptor().before(label) }
+    fn after(self, label: &'static str) -> SystemDescriptor { self.into_descriptor().after(label) }
+}
+
+impl<Marker, S: IntoSystem<Marker>> IntoSystemDescriptor<Marker> for S {
+    fn into_descriptor(self) -> SystemDescriptor {
+        SystemDescriptor { system: self.into_system(), labels: Vec::new(), before: Vec::new(), after: Vec::new() }
+    }
+}
+
+impl IntoSystemDescriptor<IsSystemDescriptor> for SystemDescriptor {
+    fn into_descriptor(self) -> SystemDescriptor { self }
+}
+
+#[derive(Default)]
+struct StageSystems {
+    systems: Vec<SystemDescriptor>,
+    /// Indices into `systems` in execution order, valid while not `dirty`.
+    order: Vec<usize>,
+    /// Consecutive runs of `order` that may execute concurrently.
+    batches: Vec<Vec<usize>>,
+    dirty: bool,
+}
+
+impl StageSystems {
+    fn build(&mut self) -> Result<(), NecsError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let len = self.systems.len();
+        let labelled = |label: &str| -> Result<Vec<usize>, NecsError> {
+            let systems: Vec<usize> = (0..len).filter(|index| self.systems[*index].labels.contains(&label)).collect();
+            if systems.is_empty() { Err(NecsError::UnknownLabel { label: label.to_string() }) } else { Ok(systems) }
+        };
+        let mut successors = vec![Vec::new(); len];
+        let mut predecessors = vec![0usize; len];
+        for (index, descriptor) in self.systems.iter().enumerate() {
+            for label in &descriptor.before {
+                for successor in labelled(label)? {
+                    successors[index].push(successor);
+                    predecessors[successor] += 1;
+                }
+            }
+            for label in &descriptor.after {
+                for predecessor in labelled(label)? {
+                    successors[predecessor].push(index);
+                    predecessors[index] += 1;
+                }
+            }
+        }
+        // Kahn's algorithm, always picking the earliest added ready system to keep insertion order where unconstrained
+        let mut ready: Vec<usize> = (0..len).filter(|index| predecessors[*index] == 0).collect();
+        let mut order = Vec::with_capacity(len);
+        while let Some(position) = ready.iter().enumerate().min_by_key(|(_, index)| **index).map(|(position, _)| position) {
+            let index = ready.swap_remove(position);
+            order.push(index);
+            for successor in &successors[index] {
+                predecessors[*successor] -= 1;
+                if predecessors[*successor] == 0 {
+                    ready.push(*successor);
+                }
+            }
+        }
+        if order.len() != len {
+            let systems = (0..len).filter(|index| predecessors[*index] > 0).map(|index| self.systems[index].system.name().to_string()).collect();
+            return Err(NecsError::ScheduleCycle { systems });
+        }
+        // A system joins the current batch only if it is compatible with every system in it and ordered after none of them
+        let mut batches: Vec<Vec<usize>> = Vec::new();
+        for index in &order {
+            let system = &self.systems[*index].system;
+            let joins = batches.last().is_some_and(|batch| {
+                !system.is_exclusive() && batch.iter().all(|other| {
+                    let other_system = &self.systems[*other].system;
+                    !other_system.is_exclusive() && other_system.access().is_compatible(system.access()) && !successors[*other].contains(index)
+                })
+            });
+            match batches.last_mut() {
+                Some(batch) if joins => batch.push(*index),
+                _ => batches.push(vec![*index]),
+            }
+        }
+        self.order = order;
+        self.batches = batches;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Runs the systems on `pool` if there is one, in order on this thread otherwise.
+    fn run(&mut self, registry: &mut Registry, pool: Option<&ThreadPool>) {
+        match pool {
+            None => {
+                for index in &self.order {
+                    let system = &mut self.systems[*index].system;
+                    let tick = registry.change_tick();
+                    system.run(registry);
+                    system.set_last_run(tick);
+                    registry.advance_tick();
+                }
+            }
+            Some(pool) => {
+                for batch in &self.batches {
+                    let tick = registry.change_tick();
+                    if let [index] = batch[..] {
+                        self.systems[index].system.run(registry);
+                    } else {
+                        let systems = self.systems.iter_mut().enumerate()
+                            .filter(|(index, _)| batch.contains(index)).map(|(_, descriptor)| &mut descriptor.system).collect();
+                        pool.run_batch(systems, registry);
+                    }
+                    for index in batch {
+                        self.systems[*index].system.set_last_run(tick);
+                    }
+                    registry.advance_tick();
+                }
+            }
+        }
+    }
+}
+
+/// Ordered stages of systems, run against a registry.
+#[derive(Default)]
+pub struct Schedule {
+    stages: BTreeMap<Stage, StageSystems>,
+    executor: Executor,
+    /// The workers of the parallel executor, once it ran.
+    pool: Option<ThreadPool>,
+    started: bool,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_executor(executor: Executor) -> Self {
+        Self { executor, ..Self::default() }
+    }
+
+    pub fn set_executor(&mut self, executor: Executor) -> &mut Self {
+        self.executor = executor;
+        self.pool = None;
+        self
+    }
+
+    pub fn add_system<Marker>(&mut self, stage: Stage, system: impl IntoSystemDescriptor<Marker>) -> &mut Self {
+        let stage = self.stages.entry(stage).or_default();
+        stage.systems.push(system.into_descriptor());
+        stage.dirty = true;
+        self
+    }
+
+    /// Orders the systems of every stage, failing on unknown labels and ordering cycles.
+    pub fn build(&mut self) -> Result<(), NecsError> {
+        self.stages.values_mut().try_for_each(StageSystems::build)
+    }
+
+    /// Names of the systems of a stage, in execution order.
+    pub fn systems(&mut self, stage: Stage) -> Result<Vec<&str>, NecsError> {
+        self.build()?;
+        Ok(self.stages.get(&stage).map_or_else(Vec::new, |stage| {
+            stage.order.iter().map(|index| stage.systems[*index].system.name()).collect()
+        }))
+    }
+
+    /// Names of the systems of a stage, grouped by the batches the parallel executor runs concurrently.
+    pub fn batches(&mut self, stage: Stage) -> Result<Vec<Vec<&str>>, NecsError> {
+        self.build()?;
+        Ok(self.stages.get(&stage).map_or_else(Vec::new, |stage| {
+            stage.batches.iter().map(|batch| batch.iter().map(|index| stage.systems[*index].system.name()).collect()).collect()
+        }))
+    }
+
+    /// Runs the startup stage on the first call, then every update stage in order.
+    pub fn run(&mut self, registry: &mut Registry) -> Result<(), NecsError> {
+        self.build()?;
+        let pool = match self.executor {
+            Executor::SingleThreaded => None,
+            Executor::Parallel { threads } => Some(&*self.pool.get_or_insert_with(|| ThreadPool::new(threads))),
+        };
+        for (stage, systems) in self.stages.iter_mut() {
+            if *stage == Stage::Startup && self.started {
+                continue;
+            }
+            systems.run(registry, pool);
+        }
+        self.started = true;
+        Ok(())
+    }
+}
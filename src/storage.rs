@@ -0,0 +1,385 @@
+use std::any::Any;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::ptr::NonNull;
+
+use crate::cell::SharedCell;
+use crate::{ComponentTicks, ComponentTrait, Entity, Tick};
+
+pub(crate) trait ComponentStorage: Send + Sync {
+    fn remove(&mut self, entity: &Entity, order: IterationOrder);
+    fn contains(&self, entity: &Entity) -> bool;
+    fn is_empty(&self) -> bool;
+    fn len(&self) -> usize;
+    fn entities(&self) -> &[Entity];
+    fn index_of(&self, entity: &Entity) -> Option<usize>;
+    fn swap(&mut self, a: usize, b: usize);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Moves the entry at `order[i]` to `i`, for every `i` in `order`.
+    fn permute(&mut self, order: &[usize]) {
+        for index in 0..order.len() {
+            // Entries before `index` are in place, so one that was taken from there has since been swapped further
+            let mut source = order[index];
+            while source < index {
+                source = order[source];
+            }
+            self.swap(index, source);
+        }
+    }
+}
+
+/// Where the components of a type are stored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    /// A packed sparse set per type: cheap to add and remove, probed entity by entity by views over several types.
+    #[default]
+    SparseSet,
+    /// Archetype tables shared by the entities having the same table-stored components: views walk the matching
+    /// tables row by row, while adding and removing a component moves the entity to another table.
+    Table,
+    /// The set of entities having the component, without values: for zero-sized marker components, which are
+    /// handed out by reference without ever being stored.
+    Tag,
+}
+
+/// Order in which storages keep their entities, and so in which views visit them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IterationOrder {
+    /// Removing an entity moves the last one into its place: the cheapest, and the same from run to run for the same
+    /// operations, but not the order the entities were added in.
+    #[default]
+    Packed,
+    /// Removing an entity shifts the ones after it, so sparse sets and archetype tables keep the order components were
+    /// added in, at the cost of a linear removal. Groups and sorting still reorder the storages they manage.
+    Insertion,
+}
+
+impl IterationOrder {
+    pub(crate) fn remove<T>(self, vec: &mut Vec<T>, index: usize) -> T {
+        match self {
+            IterationOrder::Packed => vec.swap_remove(index),
+            IterationOrder::Insertion => vec.remove(index),
+        }
+    }
+
+    /// The positions whose entries moved when the one at `index` was removed from a vector now `len` long.
+    pub(crate) fn moved(self, index: usize, len: usize) -> Range<usize> {
+        match self {
+            IterationOrder::Packed => index..(index + 1).min(len),
+            IterationOrder::Insertion => index..len,
+        }
+    }
+}
+
+/// Owns a type-erased storage, see [`SharedCell`].
+pub(crate) type StorageCell = SharedCell<dyn ComponentStorage>;
+
+impl StorageCell {
+    pub fn sparse_set<T: ComponentTrait>() -> Self {
+        SharedCell::new(Box::new(SparseSet::<T>::new()))
+    }
+
+    pub fn tags<T: ComponentTrait>() -> Self {
+        SharedCell::new(Box::new(TagSet::<T>::new()))
+    }
+}
+
+/// Packed component storage: `sparse` maps an entity index to a position in the `dense`/`data`/`ticks` arrays,
+/// which are kept contiguous by swap-removing on removal.
+#[derive(Debug)]
+pub struct SparseSet<T> {
+    sparse: Vec<Option<usize>>,
+    dense: Vec<Entity>,
+    data: Vec<T>,
+    ticks: Vec<ComponentTicks>,
+    /// Taken again whenever `data` or `ticks` may have moved.
+    ptrs: ComponentPtrs<T>,
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        let (mut data, mut ticks) = (Vec::new(), Vec::new());
+        let ptrs = ComponentPtrs::new(&mut data, &mut ticks);
+        Self { sparse: Vec::new(), dense: Vec::new(), data, ticks, ptrs }
+    }
+}
+
+impl<T> SparseSet<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize { self.dense.len() }
+    pub fn is_empty(&self) -> bool { self.dense.is_empty() }
+    pub fn entities(&self) -> &[Entity] { &self.dense }
+    pub fn values(&self) -> &[T] { &self.data }
+
+    pub fn index_of(&self, entity: Entity) -> Option<usize> {
+        let index = (*self.sparse.get(entity.index() as usize)?)?;
+        if self.dense[index] == entity { Some(index) } else { None }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.index_of(entity).is_some()
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.index_of(entity).map(|index| &self.data[index])
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.index_of(entity).map(move |index| &mut self.data[index])
+    }
+
+    pub fn ticks(&self, entity: Entity) -> Option<ComponentTicks> {
+        self.index_of(entity).map(|index| self.ticks[index])
+    }
+
+    /// Like `get_mut`, stamping the component as changed at `tick`.
+    pub fn get_mut_changed(&mut self, entity: Entity, tick: Tick) -> Option<&mut T> {
+        let index = self.index_of(entity)?;
+        self.ticks[index].changed = tick;
+        Some(&mut self.data[index])
+    }
+
+    /// Inserts the component, returning the previous value if the entity already had one.
+    pub fn insert(&mut self, entity: Entity, value: T, tick: Tick) -> Option<T> {
+        if let Some(index) = self.index_of(entity) {
+            self.ticks[index].changed = tick;
+            return Some(std::mem::replace(&mut self.data[index], value));
+        }
+        let slot = entity.index() as usize;
+        if slot >= self.sparse.len() {
+            self.sparse.resize(slot + 1, None);
+        }
+        self.sparse[slot] = Some(self.dense.len());
+        self.dense.push(entity);
+        self.data.push(value);
+        self.ticks.push(ComponentTicks::new(tick));
+        self.ptrs = ComponentPtrs::new(&mut self.data, &mut self.ticks);
+        None
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.remove_in_order(entity, IterationOrder::Packed)
+    }
+
+    /// Removes the component, keeping the other entries in `order`.
+    pub fn remove_in_order(&mut self, entity: Entity, order: IterationOrder) -> Option<T> {
+        let index = self.index_of(entity)?;
+        self.sparse[entity.index() as usize] = None;
+        order.remove(&mut self.dense, index);
+        let value = order.remove(&mut self.data, index);
+        order.remove(&mut self.ticks, index);
+        for index in order.moved(index, self.dense.len()) {
+            self.sparse[self.dense[index].index() as usize] = Some(index);
+        }
+        Some(value)
+    }
+
+    /// Swaps two entries, keeping `sparse` pointing at them.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.dense.swap(a, b);
+        self.data.swap(a, b);
+        self.ticks.swap(a, b);
+        self.sparse[self.dense[a].index() as usize] = Some(a);
+        self.sparse[self.dense[b].index() as usize] = Some(b);
+    }
+
+    /// What views need to fetch from the set, see [`SparseSetPtr`].
+    pub(crate) fn ptr(&self) -> SparseSetPtr<'_, T> {
+        SparseSetPtr { sparse: &self.sparse, dense: &self.dense, components: self.ptrs }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.dense.iter().copied().zip(self.data.iter())
+    }
+}
+
+impl<T: ComponentTrait> ComponentStorage for SparseSet<T> {
+    fn remove(&mut self, entity: &Entity, order: IterationOrder) { self.remove_in_order(*entity, order); }
+    fn contains(&self, entity: &Entity) -> bool { self.contains(*entity) }
+    fn is_empty(&self) -> bool { self.is_empty() }
+    fn len(&self) -> usize { self.len() }
+    fn entities(&self) -> &[Entity] { self.entities() }
+    fn index_of(&self, entity: &Entity) -> Option<usize> { self.index_of(*entity) }
+    fn swap(&mut self, a: usize, b: usize) { self.swap(a, b) }
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+/// Storage of zero-sized components: the entities having one and their ticks, but no values.
+pub struct TagSet<T> {
+    set: SparseSet<()>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: ComponentTrait> TagSet<T> {
+    pub fn new() -> Self {
+        Self { set: SparseSet::new(), _marker: PhantomData }
+    }
+
+    pub fn len(&self) -> usize { self.set.len() }
+    pub fn is_empty(&self) -> bool { self.set.is_empty() }
+    pub fn entities(&self) -> &[Entity] { self.set.entities() }
+    pub fn contains(&self, entity: Entity) -> bool { self.set.contains(entity) }
+    pub fn ticks(&self, entity: Entity) -> Option<ComponentTicks> { self.set.ticks(entity) }
+    pub fn index_of(&self, entity: Entity) -> Option<usize> { self.set.index_of(entity) }
+    pub fn swap(&mut self, a: usize, b: usize) { self.set.swap(a, b) }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        // SAFETY: `T` is zero-sized, so any aligned pointer refers to a valid value
+        self.contains(entity).then(|| unsafe { NonNull::dangling().as_ref() })
+    }
+
+    pub fn get_mut_changed(&mut self, entity: Entity, tick: Tick) -> Option<&mut T> {
+        self.set.get_mut_changed(entity, tick)?;
+        Some(unsafe { NonNull::dangling().as_mut() })
+    }
+
+    /// Inserts the component, returning the previous value if the entity already had one.
+    pub fn insert(&mut self, entity: Entity, value: T, tick: Tick) -> Option<T> {
+        // The value is dropped when the entity loses it, like a stored one
+        std::mem::forget(value);
+        self.set.insert(entity, (), tick).map(|()| unsafe { Self::value() })
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.remove_in_order(entity, IterationOrder::Packed)
+    }
+
+    pub fn remove_in_order(&mut self, entity: Entity, order: IterationOrder) -> Option<T> {
+        self.set.remove_in_order(entity, order).map(|()| unsafe { Self::value() })
+    }
+
+    /// Like [`SparseSet::ptr`], for values that are not stored.
+    pub(crate) fn ptr(&self) -> SparseSetPtr<'_, T> {
+        let SparseSetPtr { sparse, dense, components } = self.set.ptr();
+        SparseSetPtr { sparse, dense, components: ComponentPtrs { data: NonNull::dangling(), ticks: components.ticks } }
+    }
+}
+
+impl<T> TagSet<T> {
+    /// # Safety
+    /// Every value conjured must stand for one that was forgotten.
+    unsafe fn value() -> T {
+        debug_assert_eq!(std::mem::size_of::<T>(), 0);
+        std::ptr::read(NonNull::dangling().as_ptr())
+    }
+}
+
+impl<T> Drop for TagSet<T> {
+    fn drop(&mut self) {
+        if std::mem::needs_drop::<T>() {
+            for _ in 0..self.set.len() {
+                // SAFETY: each entity still tagged stands for one value forgotten on insertion
+                drop(unsafe { Self::value() });
+            }
+        }
+    }
+}
+
+impl<T: ComponentTrait> ComponentStorage for TagSet<T> {
+    fn remove(&mut self, entity: &Entity, order: IterationOrder) { self.remove_in_order(*entity, order); }
+    fn contains(&self, entity: &Entity) -> bool { self.contains(*entity) }
+    fn is_empty(&self) -> bool { self.is_empty() }
+    fn len(&self) -> usize { self.len() }
+    fn entities(&self) -> &[Entity] { self.entities() }
+    fn index_of(&self, entity: &Entity) -> Option<usize> { self.index_of(*entity) }
+    fn swap(&mut self, a: usize, b: usize) { self.swap(a, b) }
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+/// The index arrays of a sparse or tag set, borrowed shared, and pointers to its components: all a view fetching
+/// from the set needs, so that fetching never borrows the set itself.
+pub struct SparseSetPtr<'r, T> {
+    sparse: &'r [Option<usize>],
+    dense: &'r [Entity],
+    components: ComponentPtrs<T>,
+}
+
+impl<T> Clone for SparseSetPtr<'_, T> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<T> Copy for SparseSetPtr<'_, T> {}
+
+impl<'r, T> SparseSetPtr<'r, T> {
+    pub fn entities(&self) -> &'r [Entity] { self.dense }
+
+    /// Pointers to the component of `entity`. `hint` is where the entity may be, saving the lookup when it is.
+    pub fn component_ptr(&self, entity: Entity, hint: Option<usize>) -> Option<ComponentPtr<T>> {
+        let index = match hint {
+            Some(index) if self.dense.get(index) == Some(&entity) => index,
+            _ => (*self.sparse.get(entity.index() as usize)?).filter(|index| self.dense[*index] == entity)?,
+        };
+        // SAFETY: `index` is that of an entity of the set
+        Some(unsafe { self.components.at(index) })
+    }
+}
+
+/// Pointers into the buffers of the vectors holding components and their ticks, valid until the vectors move.
+///
+/// They are taken while the vectors are borrowed mutably, so writing through them is allowed, and hold no borrow
+/// of the vectors or the storage owning them: threads fetching different positions at once never alias.
+#[derive(Debug)]
+pub struct ComponentPtrs<T> {
+    /// Dangling for zero-sized values that are not stored.
+    data: NonNull<T>,
+    ticks: NonNull<ComponentTicks>,
+}
+
+impl<T> Clone for ComponentPtrs<T> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<T> Copy for ComponentPtrs<T> {}
+
+// SAFETY: the pointers stand for the storage owning the vectors, and dereferencing them is up to callers
+unsafe impl<T: Send + Sync> Send for ComponentPtrs<T> {}
+unsafe impl<T: Send + Sync> Sync for ComponentPtrs<T> {}
+
+impl<T> ComponentPtrs<T> {
+    pub(crate) fn new(data: &mut Vec<T>, ticks: &mut Vec<ComponentTicks>) -> Self {
+        // SAFETY: the buffer of a vector is never null
+        unsafe { Self { data: NonNull::new_unchecked(data.as_mut_ptr()), ticks: NonNull::new_unchecked(ticks.as_mut_ptr()) } }
+    }
+
+    /// # Safety
+    /// `index` must be in bounds of both vectors.
+    pub(crate) unsafe fn at(self, index: usize) -> ComponentPtr<T> {
+        ComponentPtr { data: self.data.add(index), ticks: self.ticks.add(index) }
+    }
+}
+
+/// Pointers to a stored component and its ticks, valid until the storage holding them changes shape.
+pub struct ComponentPtr<T> {
+    data: NonNull<T>,
+    ticks: NonNull<ComponentTicks>,
+}
+
+impl<T> ComponentPtr<T> {
+    /// # Safety
+    /// No mutable reference to the component may be alive.
+    pub unsafe fn ticks(&self) -> ComponentTicks {
+        *self.ticks.as_ptr()
+    }
+
+    /// # Safety
+    /// No mutable reference to the component may be alive for `'r`.
+    pub unsafe fn get<'r>(self) -> &'r T {
+        self.data.as_ref()
+    }
+
+    /// Stamps the component as changed at `tick`.
+    ///
+    /// # Safety
+    /// Nothing else may borrow the component for `'r`.
+    pub unsafe fn get_mut<'r>(self, tick: Tick) -> &'r mut T {
+        (*self.ticks.as_ptr()).changed = tick;
+        &mut *self.data.as_ptr()
+    }
+}
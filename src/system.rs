@@ -0,0 +1,190 @@
+use std::any::type_name;
+use std::marker::PhantomData;
+
+use crate::{Access, Fetch, Registry, Res, ResMut, Resource, Tick, View};
+
+/// A unit of work run by a [`Schedule`](crate::Schedule).
+///
+/// # Safety
+/// `run_unchecked` must not touch more of the registry than `access` declares.
+pub unsafe trait System: Send + 'static {
+    fn name(&self) -> &str;
+    fn access(&self) -> &Access;
+    /// Exclusive systems need the whole registry and never run alongside others.
+    fn is_exclusive(&self) -> bool;
+    /// Tick of the last time the system ran, which its change filters compare against.
+    fn last_run(&self) -> Tick;
+    fn set_last_run(&mut self, tick: Tick);
+    fn run(&mut self, registry: &mut Registry);
+    /// # Safety
+    /// The system must not be exclusive, and no one else may access what its `access` writes,
+    /// nor write what it reads, while it runs.
+    unsafe fn run_unchecked(&mut self, registry: &Registry);
+}
+
+/// A value a system function receives, built from the registry every time the system runs.
+///
+/// # Safety
+/// `access` must declare everything `get` hands out.
+pub unsafe trait SystemParam {
+    type Item<'r>;
+    fn access(access: &mut Access);
+    /// # Safety
+    /// Nothing `access` declares as written may be borrowed elsewhere for `'r`.
+    unsafe fn get<'r>(registry: &'r Registry, last_run: Tick) -> Self::Item<'r>;
+}
+
+unsafe impl<F: Fetch + 'static> SystemParam for View<'_, F> {
+    type Item<'r> = View<'r, F>;
+
+    fn access(access: &mut Access) { F::access(access); }
+
+    unsafe fn get<'r>(registry: &'r Registry, last_run: Tick) -> Self::Item<'r> {
+        View::new_unchecked(registry, last_run)
+    }
+}
+
+/// Panics when the system runs if the resource does not exist.
+unsafe impl<R: Resource> SystemParam for Res<'_, R> {
+    type Item<'r> = Res<'r, R>;
+
+    fn access(access: &mut Access) { access.read_resource::<R>(); }
+
+    unsafe fn get<'r>(registry: &'r Registry, last_run: Tick) -> Self::Item<'r> {
+        let Some(value) = registry.resource::<R>() else { panic!("resource {} does not exist", type_name::<R>()) };
+        Res::new(value, registry.resource_ticks::<R>().unwrap(), last_run)
+    }
+}
+
+/// Panics when the system runs if the resource does not exist.
+unsafe impl<R: Resource> SystemParam for ResMut<'_, R> {
+    type Item<'r> = ResMut<'r, R>;
+
+    fn access(access: &mut Access) { access.write_resource::<R>(); }
+
+    unsafe fn get<'r>(registry: &'r Registry, last_run: Tick) -> Self::Item<'r> {
+        let Some((mut value, mut ticks)) = registry.resource_ptr::<R>() else { panic!("resource {} does not exist", type_name::<R>()) };
+        ResMut::new(value.as_mut(), ticks.as_mut(), last_run, registry.change_tick())
+    }
+}
+
+/// A function whose parameters are all [`SystemParam`]s.
+pub trait SystemParamFunction<Params: SystemParam>: Send + 'static {
+    /// # Safety
+    /// See [`SystemParam::get`].
+    unsafe fn run(&mut self, registry: &Registry, last_run: Tick);
+}
+
+macro_rules! impl_system_param_function {
+    ( $( $P:ident ),* ) => {
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        unsafe impl<$( $P: SystemParam ),*> SystemParam for ( $( $P, )* ) {
+            type Item<'r> = ( $( $P::Item<'r>, )* );
+
+            fn access(access: &mut Access) {
+                $( $P::access(access); )*
+            }
+
+            unsafe fn get<'r>(registry: &'r Registry, last_run: Tick) -> Self::Item<'r> {
+                ( $( $P::get(registry, last_run), )* )
+            }
+        }
+
+        #[allow(non_snake_case, unused_variables, clippy::too_many_arguments)]
+        impl<Func, $( $P: SystemParam ),*> SystemParamFunction<( $( $P, )* )> for Func
+            where Func: FnMut($( $P ),*) + FnMut($( $P::Item<'_> ),*) + Send + 'static
+        {
+            unsafe fn run(&mut self, registry: &Registry, last_run: Tick) {
+                // Calling through a generic function makes the compiler pick the `FnMut(Item<'_>)` signature
+                fn call<$( $P ),*>(mut func: impl FnMut($( $P ),*), $( $P: $P ),*) {
+                    func($( $P ),*)
+                }
+                let ( $( $P, )* ) = <( $( $P, )* ) as SystemParam>::get(registry, last_run);
+                call(self, $( $P ),*)
+            }
+        }
+    }
+}
+
+impl_system_param_function!();
+impl_system_param_function!(A);
+impl_system_param_function!(A, B);
+impl_system_param_function!(A, B, C);
+impl_system_param_function!(A, B, C, D);
+impl_system_param_function!(A, B, C, D, E);
+impl_system_param_function!(A, B, C, D, E, F);
+impl_system_param_function!(A, B, C, D, E, F, G);
+impl_system_param_function!(A, B, C, D, E, F, G, H);
+
+pub struct FunctionSystem<Func, Params> {
+    func: Func,
+    name: &'static str,
+    access: Access,
+    last_run: Tick,
+    _marker: PhantomData<fn() -> Params>,
+}
+
+unsafe impl<Func: SystemParamFunction<Params>, Params: SystemParam + 'static> System for FunctionSystem<Func, Params> {
+    fn name(&self) -> &str { self.name }
+    fn access(&self) -> &Access { &self.access }
+    fn is_exclusive(&self) -> bool { false }
+    fn last_run(&self) -> Tick { self.last_run }
+    fn set_last_run(&mut self, tick: Tick) { self.last_run = tick; }
+
+    fn run(&mut self, registry: &mut Registry) {
+        // SAFETY: the registry is borrowed exclusively
+        unsafe { self.run_unchecked(registry) }
+    }
+
+    unsafe fn run_unchecked(&mut self, registry: &Registry) {
+        self.func.run(registry, self.last_run);
+    }
+}
+
+pub struct ExclusiveSystem<Func> {
+    func: Func,
+    name: &'static str,
+    access: Access,
+    last_run: Tick,
+}
+
+unsafe impl<Func: FnMut(&mut Registry) + Send + 'static> System for ExclusiveSystem<Func> {
+    fn name(&self) -> &str { self.name }
+    fn access(&self) -> &Access { &self.access }
+    fn is_exclusive(&self) -> bool { true }
+    fn last_run(&self) -> Tick { self.last_run }
+    fn set_last_run(&mut self, tick: Tick) { self.last_run = tick; }
+
+    fn run(&mut self, registry: &mut Registry) {
+        (self.func)(registry);
+    }
+
+    unsafe fn run_unchecked(&mut self, _registry: &Registry) {
+        panic!("exclusive system {} needs the registry borrowed mutably", self.name);
+    }
+}
+
+pub struct IsFunctionSystem;
+pub struct IsExclusiveSystem;
+
+pub trait IntoSystem<Marker> {
+    fn into_system(self) -> Box<dyn System>;
+}
+
+impl<Func: SystemParamFunction<Params>, Params: SystemParam + 'static> IntoSystem<(IsFunctionSystem, Params)> for Func {
+    fn into_system(self) -> Box<dyn System> {
+        let name = type_name::<Func>();
+        let mut access = Access::default();
+        Params::access(&mut access);
+        if let Some(component) = access.conflicts().first() {
+            panic!("system {}: component {} is borrowed mutably more than once", name, component);
+        }
+        Box::new(FunctionSystem { func: self, name, access, last_run: 0, _marker: PhantomData::<fn() -> Params> })
+    }
+}
+
+impl<Func: FnMut(&mut Registry) + Send + 'static> IntoSystem<IsExclusiveSystem> for Func {
+    fn into_system(self) -> Box<dyn System> {
+        Box::new(ExclusiveSystem { func: self, name: type_name::<Func>(), access: Access::default(), last_run: 0 })
+    }
+}
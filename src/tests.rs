@@ -12,6 +12,9 @@ struct Velocity {
     dy: i32,
 }
 
+#[derive(Debug, Default, PartialEq)]
+struct Frozen;
+
 #[derive(Debug, Default, PartialEq)]
 struct Color {
     r: u8,
@@ -19,9 +22,25 @@ struct Color {
     b: u8,
 }
 
+/// Tells whether `T` is a component: the inherent method is only picked when its bound holds.
+struct Probe<T>(std::marker::PhantomData<T>);
+
+trait NotComponent {
+    fn is_component(&self) -> bool { false }
+}
+
+impl<T> NotComponent for Probe<T> {}
+
+impl<T: ComponentTrait> Probe<T> {
+    fn is_component(&self) -> bool { true }
+}
+
 #[test]
 fn registry() {
     let mut registry = Registry::new();
+    let stale = registry.create();
+    registry.add(stale, Position::default());
+    registry.destroy(stale);
     let entity = registry.create();
 
     registry.add(entity, Position { x: 10, y: 20 });
@@ -30,10 +49,10 @@ fn registry() {
     registry.replace(entity, Position { x: 40, y: 80 });
     registry.remove::<Color>(entity);
 
-    assert!(!registry.exists(10));
-    assert!(registry.get::<Position>(10).is_none());
-    assert!(registry.get::<Velocity>(10).is_none());
-    assert!(registry.get::<Color>(10).is_none());
+    assert!(!registry.exists(stale));
+    assert!(registry.get::<Position>(stale).is_none());
+    assert!(registry.get::<Velocity>(stale).is_none());
+    assert!(registry.get::<Color>(stale).is_none());
 
     assert!(registry.exists(entity));
     assert_eq!(registry.get::<Position>(entity), Some(&Position { x: 40, y: 80 }));
@@ -51,6 +70,61 @@ fn registry() {
     assert!(registry.get::<Color>(entity).is_none());
 }
 
+#[test]
+fn entity_recycling() {
+    let mut registry = Registry::new();
+    let first = registry.create();
+    let second = registry.create();
+    assert_ne!(first, second);
+
+    registry.destroy(first);
+    registry.destroy(first);
+    assert!(!registry.exists(first));
+
+    let recycled = registry.create();
+    assert_eq!(recycled.index(), first.index());
+    assert_eq!(recycled.generation(), first.generation() + 1);
+    assert_ne!(recycled, first);
+    assert!(registry.exists(recycled));
+    assert!(!registry.exists(first));
+
+    registry.add(recycled, Position { x: 1, y: 2 });
+    assert!(registry.get::<Position>(first).is_none());
+    registry.add(first, Velocity::default());
+    assert!(registry.get::<Velocity>(recycled).is_none());
+
+    let fresh = registry.create();
+    assert_ne!(fresh.index(), first.index());
+    assert_ne!(fresh.index(), second.index());
+}
+
+#[test]
+fn sparse_set() {
+    let mut registry = Registry::new();
+    let entities: Vec<Entity> = (0..4).map(|_| registry.create()).collect();
+    let mut set = SparseSet::new();
+    for (i, entity) in entities.iter().enumerate() {
+        assert_eq!(set.insert(*entity, i, 1), None);
+    }
+    assert_eq!(set.insert(entities[2], 20, 2), Some(2));
+    assert_eq!(set.len(), 4);
+
+    assert_eq!(set.remove(entities[1]), Some(1));
+    assert_eq!(set.remove(entities[1]), None);
+    assert_eq!(set.entities(), &[entities[0], entities[3], entities[2]]);
+    assert_eq!(set.values(), &[0, 3, 20]);
+    assert_eq!(set.get(entities[3]), Some(&3));
+    assert_eq!(set.ticks(entities[2]), Some(ComponentTicks { added: 1, changed: 2 }));
+    assert!(!set.contains(entities[1]));
+
+    registry.destroy(entities[0]);
+    let recycled = registry.create();
+    assert_eq!(recycled.index(), entities[0].index());
+    assert!(set.contains(entities[0]));
+    assert!(!set.contains(recycled));
+    assert_eq!(set.get(recycled), None);
+}
+
 #[test]
 fn registry2() {
     let mut registry = Registry::new();
@@ -95,35 +169,1198 @@ fn get_tuple() {
 
 #[test]
 fn view() {
+    let mut registry = Registry::new();
+    let first = registry.create();
+    registry.add(first, Position { x: 10, y: 20 });
+    registry.add(first, Velocity { dx: -50, dy: -100 });
+    registry.add(first, Color::default());
+    let second = registry.create();
+    registry.add(second, Position { x: 20, y: 30 });
+    registry.add(second, Velocity { dx: -60, dy: -200 });
+    let third = registry.create();
+    registry.add(third, Position { x: 20, y: 30 });
+    registry.add(third, Color::default());
+    let fourth = registry.create();
+    registry.add(fourth, Position { x: 30, y: 50 });
+    registry.add(fourth, Velocity { dx: -80, dy: -500 });
+    registry.add(fourth, Color::default());
+
+    let all = <(Position, )>::view_entities(&registry);
+    assert_eq!(all.len(), 4);
+    assert_eq!(all.map(|(entity, _)| entity).collect::<Vec<_>>(), [first, second, third, fourth]);
+
+    let mut moving = Vec::new();
+    for (entity, (_position, _velocity)) in registry.view_all::<(Position, Velocity)>() {
+        moving.push(entity);
+    }
+    assert_eq!(moving, [first, second, fourth]);
+
+    let mut colored = Vec::new();
+    registry.view_all::<(Position, Velocity, Color)>().iter().for_each(|(entity, (_position, _velocity, _color))| {
+        colored.push(entity);
+    });
+    assert_eq!(colored, [first, fourth]);
+}
+
+#[test]
+fn view_iterator() {
+    let mut registry = Registry::new();
+    let entities: Vec<Entity> = (0..5).map(|i| registry.create_with((Position { x: i, y: i },))).collect();
+    registry.add(entities[1], Velocity { dx: 1, dy: 1 });
+    registry.add(entities[3], Velocity { dx: 3, dy: 3 });
+
+    let view = registry.view_all::<(Position, Velocity)>();
+    assert_eq!(view.len(), 2);
+    assert_eq!(view.size_hint(), (0, Some(2)));
+    assert!(!view.is_empty());
+    assert!(view.contains(entities[1]));
+    assert!(!view.contains(entities[0]));
+    assert_eq!(view.get(entities[3]), Some((&Position { x: 3, y: 3 }, &Velocity { dx: 3, dy: 3 })));
+    assert_eq!(view.get(entities[2]), None);
+
+    let mut visited = Vec::new();
+    for (entity, (position, velocity)) in &view {
+        assert_eq!(position.x, velocity.dx);
+        visited.push(entity);
+    }
+    assert_eq!(visited, vec![entities[1], entities[3]]);
+    assert_eq!(view.count(), 2);
+
+    assert!(registry.view_all::<(Position, Color)>().is_empty());
+    assert_eq!(registry.view_all::<(Position, Color)>().next(), None);
+}
+
+#[test]
+fn view_mut() {
+    let mut registry = Registry::new();
+    let moving = registry.create_with((Position { x: 1, y: 1 }, Velocity { dx: 2, dy: -1 }));
+    let still = registry.create_with((Position { x: 5, y: 5 },));
+    let _ = registry.create_with((Velocity { dx: 9, dy: 9 },));
+
+    for (_entity, (position, velocity)) in registry.view_mut::<(&mut Position, &Velocity)>() {
+        position.x += velocity.dx;
+        position.y += velocity.dy;
+    }
+    assert_eq!(registry.get::<Position>(moving), Some(&Position { x: 3, y: 0 }));
+    assert_eq!(registry.get::<Position>(still), Some(&Position { x: 5, y: 5 }));
+
+    let mut view = registry.view_mut::<(&mut Position, &mut Velocity)>();
+    let (_, (position, velocity)) = view.next().unwrap();
+    std::mem::swap(&mut position.x, &mut velocity.dx);
+    assert!(view.next().is_none());
+    assert_eq!(registry.get_all::<(Position, Velocity)>(moving), (Some(&Position { x: 2, y: 0 }), Some(&Velocity { dx: 3, dy: -1 })));
+
+    let positions: Vec<_> = registry.view::<(&Position,)>().map(|(_, (position,))| position.x).collect();
+    assert_eq!(positions, vec![2, 5]);
+
+    let mut items: Vec<_> = registry.view_mut::<(&mut Position,)>().map(|(_, (position,))| position).collect();
+    for position in &mut items {
+        position.y += 1;
+    }
+    let sums: Vec<_> = registry.view::<(&Position,)>()
+        .flat_map(|(_, (a,))| registry.view::<(&Position,)>().map(move |(_, (b,))| a.y + b.y))
+        .collect();
+    assert_eq!(sums, vec![2, 7, 7, 12]);
+}
+
+#[test]
+#[should_panic(expected = "borrowed mutably more than once")]
+fn view_mut_aliasing() {
+    let mut registry = Registry::new();
+    registry.create_with((Position::default(),));
+    let _ = registry.view_mut::<(&mut Position, &Position)>();
+}
+
+#[test]
+fn access() {
+    let mut movement = Access::default();
+    <(&mut Position, &Velocity)>::access(&mut movement);
+    assert!(movement.conflicts().is_empty());
+
+    let mut render = Access::default();
+    <(&Position, &Color)>::access(&mut render);
+    let mut physics = Access::default();
+    <(&mut Velocity, &Color)>::access(&mut physics);
+    assert!(!movement.is_compatible(&render));
+    assert!(!movement.is_compatible(&physics));
+    assert!(render.is_compatible(&physics));
+
+    let mut aliased = Access::default();
+    <(&mut Color, &mut Color)>::access(&mut aliased);
+    assert_eq!(aliased.conflicts(), &[std::any::type_name::<Color>()]);
+
+    // Filters do not alias the components fetched, but still order queries writing them
+    let mut filtered = Access::default();
+    <(&mut Color, Changed<Color>, With<Velocity>)>::access(&mut filtered);
+    assert!(filtered.conflicts().is_empty());
+    assert!(!filtered.is_compatible(&physics));
+    assert!(!physics.is_compatible(&filtered));
+}
+
+#[test]
+fn view_filters() {
+    let mut registry = Registry::new();
+    let moving = registry.create_with((Position::default(), Velocity { dx: 1, dy: 1 }, Color { r: 255, g: 0, b: 0 }));
+    let frozen = registry.create_with((Position::default(), Velocity { dx: 2, dy: 2 }, Frozen));
+    let plain = registry.create_with((Position::default(), Velocity { dx: 3, dy: 3 }));
+    let still = registry.create_with((Position::default(),));
+
+    let thawed: Vec<_> = registry.view::<(&Position, &Velocity, Without<Frozen>)>().map(|(entity, _)| entity).collect();
+    assert_eq!(thawed, vec![moving, plain]);
+
+    let stuck: Vec<_> = registry.view::<(&Velocity, With<Frozen>)>().map(|(entity, (velocity, ()))| (entity, velocity.dx)).collect();
+    assert_eq!(stuck, vec![(frozen, 2)]);
+
+    let colors: Vec<_> = registry.view::<(&Velocity, Option<&Color>)>().map(|(_, (_, color))| color.map(|color| color.r)).collect();
+    assert_eq!(colors, vec![Some(255), None, None]);
+
+    // Queries requiring no component walk every entity
+    assert_eq!(registry.view::<(Option<&Color>,)>().count(), 4);
+    let thawed: Vec<_> = registry.view::<(Without<Frozen>,)>().map(|(entity, _)| entity).collect();
+    assert_eq!(thawed, vec![moving, plain, still]);
+    assert_eq!(registry.view_all::<()>().count(), 4);
+    // Filters are not components, so `view_all` does not take them
+    assert!(!Probe::<With<Frozen>>(std::marker::PhantomData).is_component());
+    assert!(!Probe::<Without<Frozen>>(std::marker::PhantomData).is_component());
+
+    for (_, (position, velocity, _)) in registry.view_mut::<(&mut Position, &Velocity, Without<Frozen>)>() {
+        position.x += velocity.dx;
+    }
+    assert_eq!(registry.get::<Position>(moving).unwrap().x, 1);
+    assert_eq!(registry.get::<Position>(frozen).unwrap().x, 0);
+    assert_eq!(registry.get::<Position>(plain).unwrap().x, 3);
+
+    for (_, (_, color)) in registry.view_mut::<(&Velocity, Option<&mut Color>)>() {
+        if let Some(color) = color {
+            color.g = 128;
+        }
+    }
+    assert_eq!(registry.get::<Color>(moving), Some(&Color { r: 255, g: 128, b: 0 }));
+
+    registry.remove::<Frozen>(frozen);
+    assert_eq!(registry.view::<(&Position, Without<Frozen>)>().count(), 4);
+    assert_eq!(registry.view::<(&Position, With<Frozen>)>().count(), 0);
+    registry.destroy(plain);
+    assert_eq!(registry.view_all::<()>().iter().map(|(entity, ())| entity).collect::<Vec<_>>(), vec![moving, frozen, still]);
+}
+
+#[test]
+fn observer() {
+    use std::sync::{Arc, Mutex};
+
+    let mut registry = Registry::new();
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    let log = events.clone();
+    let construct = registry.on_construct::<Position>().connect(move |_, entity| log.lock().unwrap().push((Event::Construct, entity)));
+    let log = events.clone();
+    registry.on_update::<Position>().connect(move |_, entity| log.lock().unwrap().push((Event::Update, entity)));
+    let log = events.clone();
+    registry.on_destroy::<Position>().connect(move |registry, entity| {
+        assert!(registry.get::<Position>(entity).is_some());
+        log.lock().unwrap().push((Event::Destroy, entity));
+    });
+    assert_eq!(registry.on_update::<Position>().len(), 1);
+    assert!(registry.on_update::<Velocity>().is_empty());
+
+    let first = registry.create_with((Position::default(), Velocity::default()));
+    let second = registry.create_with((Position::default(),));
+    registry.add(first, Position { x: 1, y: 1 });
+    registry.replace(first, Position { x: 2, y: 2 });
+    registry.patch::<Position>(second).with(|position| position.x = 3);
+    registry.patch::<Velocity>(first).with(|velocity| velocity.dx = 3);
+    registry.remove::<Position>(second);
+    registry.remove::<Position>(second);
+    registry.destroy(first);
+    assert_eq!(*events.lock().unwrap(), vec![
+        (Event::Construct, first),
+        (Event::Construct, second),
+        (Event::Update, first),
+        (Event::Update, second),
+        (Event::Destroy, second),
+        (Event::Destroy, first),
+    ]);
+
+    assert!(registry.disconnect(construct));
+    assert!(!registry.disconnect(construct));
+    events.lock().unwrap().clear();
+    let third = registry.create_with((Position::default(),));
+    registry.replace(third, Position::default());
+    assert_eq!(*events.lock().unwrap(), vec![(Event::Update, third)]);
+}
+
+#[test]
+fn observer_reentrant() {
+    let mut registry = Registry::new();
+    registry.on_construct::<Position>().connect(|registry, entity| registry.add(entity, Velocity { dx: 1, dy: 1 }));
+    registry.on_destroy::<Velocity>().connect(|registry, entity| registry.remove::<Color>(entity));
+
+    let entity = registry.create_with((Position::default(), Color::default()));
+    assert_eq!(registry.get::<Velocity>(entity), Some(&Velocity { dx: 1, dy: 1 }));
+
+    registry.remove::<Velocity>(entity);
+    assert!(registry.get::<Color>(entity).is_none());
+    registry.destroy(entity);
+    assert!(!registry.exists(entity));
+}
+
+#[test]
+fn observer_collector() {
+    let mut registry = Registry::new();
+    let mut collector = registry.collector::<Position>().where_::<(Velocity,)>().exclude::<(Frozen,)>().connect();
+
+    let moving = registry.create_with((Velocity::default(), Position::default()));
+    let frozen = registry.create_with((Velocity::default(), Frozen, Position::default()));
+    let still = registry.create_with((Position::default(),));
+    assert_eq!(collector.iter().collect::<Vec<_>>(), vec![moving]);
+
+    collector.clear();
+    assert!(collector.is_empty());
+    registry.replace(frozen, Position { x: 1, y: 1 });
+    registry.patch::<Position>(still).with(|position| position.x = 1);
+    registry.add(still, Velocity::default());
+    assert!(collector.is_empty());
+    registry.patch::<Position>(still).with(|position| position.x = 2);
+    registry.remove::<Frozen>(frozen);
+    registry.replace(frozen, Position { x: 2, y: 2 });
+    assert_eq!((&collector).into_iter().collect::<Vec<_>>(), vec![still, frozen]);
+
+    registry.destroy(still);
+    assert!(!collector.contains(still));
+    assert_eq!(collector.len(), 1);
+
+    collector.disconnect(&mut registry);
+    registry.replace(moving, Position::default());
+    assert!(!collector.contains(moving));
+    assert!(registry.on_construct::<Position>().is_empty());
+
+    // A collector dropped without disconnecting is disconnected by the next event
+    drop(registry.collector::<Position>().connect());
+    assert_eq!(registry.on_construct::<Position>().len(), 1);
+    registry.replace(moving, Position { x: 1, y: 1 });
+    assert!(registry.on_construct::<Position>().is_empty());
+    assert!(registry.on_update::<Position>().is_empty() && registry.on_destroy::<Position>().is_empty());
+}
+
+#[test]
+fn change_ticks() {
+    let mut registry = Registry::new();
+    let first = registry.create_with((Position::default(), Velocity::default()));
+    let second = registry.create_with((Position::default(), Velocity::default()));
+    let tick = registry.change_tick();
+    assert_eq!(registry.ticks::<Position>(first), Some(ComponentTicks::new(tick)));
+    assert_eq!(registry.view::<(&Position, Added<Position>)>().count(), 2);
+    assert_eq!(registry.view::<(&Position, Changed<Position>)>().count(), 2);
+
+    registry.advance_tick();
+    assert_eq!(registry.change_tick(), tick + 1);
+    assert_eq!(registry.view::<(&Position, Added<Position>)>().count(), 0);
+    assert_eq!(registry.view::<(&Position, Changed<Velocity>)>().count(), 0);
+
+    registry.replace(first, Position { x: 1, y: 1 });
+    registry.patch::<Velocity>(second).with(|velocity| velocity.dx = 1);
+    let third = registry.create_with((Position::default(),));
+    assert_eq!(registry.ticks::<Position>(first), Some(ComponentTicks { added: tick, changed: tick + 1 }));
+    let added: Vec<_> = registry.view::<(Added<Position>,)>().map(|(entity, _)| entity).collect();
+    assert_eq!(added, vec![third]);
+    let changed: Vec<_> = registry.view::<(&Position, Changed<Position>)>().map(|(entity, _)| entity).collect();
+    assert_eq!(changed, vec![first, third]);
+    let changed: Vec<_> = registry.view::<(&Position, Changed<Velocity>)>().map(|(entity, _)| entity).collect();
+    assert_eq!(changed, vec![second]);
+
+    registry.advance_tick();
+    for (_, (velocity,)) in registry.view_mut::<(&mut Velocity,)>() {
+        velocity.dy += 1;
+    }
+    assert_eq!(registry.view::<(Changed<Velocity>,)>().count(), 2);
+    assert_eq!(registry.view::<(Changed<Position>,)>().count(), 0);
+
+    registry.advance_tick();
+    registry.replace(first, Position { x: 5, y: 5 });
+    for (_, (position, ())) in registry.view_mut::<(&mut Position, Changed<Position>)>() {
+        position.x += 10;
+    }
+    assert_eq!(registry.get::<Position>(first), Some(&Position { x: 15, y: 5 }));
+    assert_eq!(registry.get::<Position>(third), Some(&Position::default()));
+    Schedule::new().add_system(Stage::Update, |_: View<(&mut Position, Changed<Position>, With<Position>)>| {});
+
+    // Filters are not components, so `view_all` does not take them
+    assert!(Probe::<Position>(std::marker::PhantomData).is_component());
+    assert!(!Probe::<Added<Position>>(std::marker::PhantomData).is_component());
+    assert!(!Probe::<Changed<Position>>(std::marker::PhantomData).is_component());
+}
+
+#[test]
+fn patch_get_mut() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let mut registry = Registry::new();
+    let updates = Arc::new(AtomicUsize::new(0));
+    let counter = updates.clone();
+    registry.on_update::<Position>().connect(move |_, _| { counter.fetch_add(1, Ordering::SeqCst); });
+    let entity = registry.create_with((Position::default(),));
+    registry.advance_tick();
+
+    let mut patch = registry.patch::<Position>(entity);
+    assert!(patch.is_some());
+    {
+        let position = patch.get_mut().unwrap();
+        assert_eq!(position.x, 0);
+        assert!(!position.is_changed());
+    }
+    assert_eq!(updates.load(Ordering::SeqCst), 0);
+    {
+        let mut position = patch.get_mut().unwrap();
+        position.x = 7;
+        position.y = 8;
+        assert!(position.is_changed());
+    }
+    assert_eq!(updates.load(Ordering::SeqCst), 1);
+    assert_eq!(patch.get(), Some(&Position { x: 7, y: 8 }));
+    assert!(patch.with(|position| position.x += 1));
+    assert_eq!(updates.load(Ordering::SeqCst), 2);
+    assert_eq!(registry.get::<Position>(entity), Some(&Position { x: 8, y: 8 }));
+    assert_eq!(registry.view::<(Changed<Position>,)>().count(), 1);
+
+    let mut missing = registry.patch::<Velocity>(entity);
+    assert!(!missing.is_some());
+    assert!(missing.get_mut().is_none());
+    assert!(!missing.with(|velocity| velocity.dx = 1));
+    assert_eq!(updates.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn add_or_replace() {
     let mut registry = Registry::new();
     let entity = registry.create();
-    registry.add(entity, Position { x: 10, y: 20 });
-    registry.add(entity, Velocity { dx: -50, dy: -100 });
-    registry.add(entity, Color::default());
-    let entity = registry.create();
-    registry.add(entity, Position { x: 20, y: 30 });
-    registry.add(entity, Velocity { dx: -60, dy: -200 });
-    let entity = registry.create();
-    registry.add(entity, Position { x: 20, y: 30 });
-    registry.add(entity, Color::default());
-    let entity = registry.create();
-    registry.add(entity, Position { x: 30, y: 50 });
-    registry.add(entity, Velocity { dx: -80, dy: -500 });
-    registry.add(entity, Color::default());
+    let stale = registry.create();
+    registry.destroy(stale);
 
+    assert!(registry.try_add(entity, Position { x: 1, y: 1 }).is_ok());
+    assert_eq!(registry.try_add(entity, Position { x: 2, y: 2 }).unwrap_err().component, Position { x: 2, y: 2 });
+    assert_eq!(registry.try_add(stale, Position::default()).unwrap_err().component, Position::default());
+    assert_eq!(registry.get::<Position>(entity), Some(&Position { x: 1, y: 1 }));
 
-    let all = <(Position, )>::view_entities(&registry);
-    println!("{:?}", all);
+    registry.emplace_or_replace(entity, Position { x: 3, y: 3 });
+    registry.emplace_or_replace(entity, Velocity { dx: 3, dy: 3 });
+    assert_eq!(registry.get_all::<(Position, Velocity)>(entity), (Some(&Position { x: 3, y: 3 }), Some(&Velocity { dx: 3, dy: 3 })));
+
+    registry.get_or_insert_with(entity, || Position { x: 9, y: 9 }).x += 1;
+    assert_eq!(registry.get::<Position>(entity), Some(&Position { x: 4, y: 3 }));
+    registry.get_or_insert_with(entity, Color::default).r = 10;
+    assert_eq!(registry.get::<Color>(entity), Some(&Color { r: 10, g: 0, b: 0 }));
+
+    // Writes through the guard are reported to update listeners, reads are not
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    let updates = Arc::new(AtomicUsize::new(0));
+    let counter = updates.clone();
+    registry.on_update::<Color>().connect(move |_, _| { counter.fetch_add(1, Ordering::SeqCst); });
+    registry.get_or_insert_with(entity, Color::default).g = 20;
+    assert_eq!(registry.get_or_insert_with(entity, Color::default).g, 20);
+    assert_eq!(updates.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn clear() {
+    let mut registry = Registry::new();
+    let destroyed = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = destroyed.clone();
+    registry.on_destroy::<Position>().connect(move |_, _| { counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst); });
+    let entities: Vec<_> = (0..3).map(|_| registry.create_with((Position::default(), Velocity::default()))).collect();
+
+    registry.clear::<Position>();
+    registry.clear::<Color>();
+    assert_eq!(destroyed.load(std::sync::atomic::Ordering::SeqCst), 3);
+    assert_eq!(registry.view::<(&Position,)>().count(), 0);
+    assert_eq!(registry.view::<(&Velocity,)>().count(), 3);
+    for entity in entities {
+        assert!(registry.get::<Position>(entity).is_none());
+        assert!(registry.try_add(entity, Position::default()).is_ok());
+    }
+}
+
+#[test]
+fn fallible_api() {
+    let mut registry = Registry::new();
+    let entity = registry.create_with((Position::default(),));
+    let stale = registry.create();
+    registry.destroy(stale);
+    let position = std::any::type_name::<Position>();
+    let velocity = std::any::type_name::<Velocity>();
+
+    assert_eq!(registry.try_get::<Position>(entity), Ok(&Position::default()));
+    assert_eq!(registry.try_get::<Position>(stale), Err(NecsError::NoSuchEntity(stale)));
+    assert_eq!(registry.try_get::<Velocity>(entity), Err(NecsError::ComponentMissing { entity, type_name: velocity }));
 
-    println!("for in view");
-    for (entt, (_position, _velocity)) in registry.view_all::<(Position, Velocity)>() {
-        println!("{:?}", entt);
+    let rejected = registry.try_add(entity, Position { x: 1, y: 1 }).unwrap_err();
+    assert_eq!(rejected.error, NecsError::ComponentAlreadyPresent { entity, type_name: position });
+    assert_eq!(rejected.component, Position { x: 1, y: 1 });
+    let error: NecsError = registry.try_add(stale, Velocity::default()).unwrap_err().into();
+    assert_eq!(error, NecsError::NoSuchEntity(stale));
+
+    assert_eq!(registry.try_replace(entity, Velocity::default()), Err(NecsError::ComponentMissing { entity, type_name: velocity }));
+    assert_eq!(registry.try_replace(entity, Position { x: 2, y: 2 }), Ok(()));
+    assert_eq!(registry.get::<Position>(entity), Some(&Position { x: 2, y: 2 }));
+    assert!(registry.try_patch::<Position>(entity).unwrap().with(|position| position.y = 3));
+    assert!(registry.try_patch::<Position>(stale).is_err());
+
+    assert_eq!(registry.try_remove::<Velocity>(entity), Err(NecsError::ComponentMissing { entity, type_name: velocity }));
+    assert_eq!(registry.try_remove::<Position>(entity), Ok(()));
+    assert_eq!(registry.try_destroy(entity), Ok(()));
+    assert_eq!(registry.try_destroy(entity), Err(NecsError::NoSuchEntity(entity)));
+    assert_eq!(NecsError::NoSuchEntity(entity).to_string(), format!("entity {:?} does not exist", entity));
+}
+
+fn movement(view: View<(&mut Position, &Velocity)>) {
+    for (_, (position, velocity)) in view {
+        position.x += velocity.dx;
+        position.y += velocity.dy;
+    }
+}
+
+fn friction(view: View<(&mut Velocity,)>) {
+    for (_, (velocity,)) in view {
+        velocity.dx /= 2;
+        velocity.dy /= 2;
     }
+}
+
+fn spawn(registry: &mut Registry) {
+    registry.create_with((Position::default(), Velocity { dx: 8, dy: 4 }));
+}
+
+#[test]
+fn schedule() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let mut registry = Registry::new();
+    let mut schedule = Schedule::new();
+    let changed = Arc::new(AtomicUsize::new(0));
+    let counter = changed.clone();
+    schedule
+        .add_system(Stage::Update, friction.label("friction").after("movement"))
+        .add_system(Stage::Update, movement.label("movement"))
+        .add_system(Stage::Startup, spawn)
+        .add_system(Stage::PostUpdate, move |view: View<(&Position, Changed<Position>)>| {
+            counter.fetch_add(view.count(), Ordering::SeqCst);
+        })
+        .add_system(Stage::PreUpdate, |_: View<(&Velocity, Added<Velocity>)>| {});
+
+    assert_eq!(schedule.systems(Stage::Update).unwrap(), vec!["necst::tests::movement", "necst::tests::friction"]);
+    assert_eq!(schedule.systems(Stage::Startup).unwrap(), vec!["necst::tests::spawn"]);
+
+    schedule.run(&mut registry).unwrap();
+    schedule.run(&mut registry).unwrap();
+    let (position, velocity) = registry.view::<(&Position, &Velocity)>().next().unwrap().1;
+    assert_eq!(registry.view::<(&Position,)>().count(), 1);
+    assert_eq!((position, velocity), (&Position { x: 12, y: 6 }, &Velocity { dx: 2, dy: 1 }));
+    assert_eq!(changed.load(Ordering::SeqCst), 2);
+
+    registry.clear::<Velocity>();
+    schedule.run(&mut registry).unwrap();
+    assert_eq!(changed.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn schedule_errors() {
+    let mut schedule = Schedule::new();
+    schedule.add_system(Stage::Update, movement.label("movement").after("friction"));
+    schedule.add_system(Stage::Update, friction.label("friction").after("spawn"));
+    assert_eq!(schedule.build(), Err(NecsError::UnknownLabel { label: "spawn".to_string() }));
 
-    println!("view for_each");
-    registry.view_all::<(Position, Velocity, Color)>().iter().for_each(|(entt, (_pos, _vel, _col))| {
-        println!("{:?}", entt);
+    let mut schedule = Schedule::new();
+    schedule.add_system(Stage::Update, movement.label("movement").after("friction"));
+    schedule.add_system(Stage::Update, friction.label("friction").after("spawn"));
+    schedule.add_system(Stage::Update, spawn.label("spawn").after("movement"));
+    schedule.add_system(Stage::Update, (|| {}).before("movement"));
+    let error = schedule.run(&mut Registry::new()).unwrap_err();
+    assert_eq!(error, NecsError::ScheduleCycle {
+        systems: vec!["necst::tests::movement".to_string(), "necst::tests::friction".to_string(), "necst::tests::spawn".to_string()],
     });
+}
+
+#[test]
+#[should_panic(expected = "borrowed mutably more than once")]
+fn system_aliasing() {
+    fn aliased(_: View<(&mut Position,)>, _: View<(&Position,)>) {}
+    Schedule::new().add_system(Stage::Update, aliased);
+}
+
+#[test]
+fn parallel_executor() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    // Each system waits for the other to start, which only terminates when both run concurrently
+    fn rendezvous(own: Arc<AtomicBool>, other: Arc<AtomicBool>) -> impl FnMut() -> bool {
+        move || {
+            own.store(true, Ordering::SeqCst);
+            let start = Instant::now();
+            while !other.load(Ordering::SeqCst) {
+                if start.elapsed() > Duration::from_secs(5) {
+                    return false;
+                }
+                std::thread::yield_now();
+            }
+            true
+        }
+    }
+
+    let (a, b) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)));
+    let (met_a, met_b) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)));
+    let (mut wait_a, mut wait_b) = (rendezvous(a.clone(), b.clone()), rendezvous(b, a));
+    let (result_a, result_b) = (met_a.clone(), met_b.clone());
+
+    let mut registry = Registry::new();
+    let mut schedule = Schedule::with_executor(Executor::Parallel { threads: 2 });
+    schedule
+        .add_system(Stage::Startup, spawn)
+        // Both walk the velocities at the same time, one of them writing positions
+        .add_system(Stage::Update, move |view: View<(&mut Position, &Velocity)>| result_a.store(wait_a() && view.count() == 1, Ordering::SeqCst))
+        .add_system(Stage::Update, move |view: View<(&Velocity,)>| result_b.store(wait_b() && view.count() == 1, Ordering::SeqCst))
+        .add_system(Stage::PostUpdate, movement.label("movement"))
+        .add_system(Stage::PostUpdate, friction.after("movement"))
+        .add_system(Stage::PostUpdate, |_: View<(&Color,)>| {})
+        .add_system(Stage::PostUpdate, |registry: &mut Registry| registry.advance_tick())
+        .add_system(Stage::PostUpdate, |_: View<(&Position,)>| {})
+        .add_system(Stage::PostUpdate, |_: View<(&Frozen,)>| {});
+    schedule.run(&mut registry).unwrap();
+    assert!(met_a.load(Ordering::SeqCst) && met_b.load(Ordering::SeqCst));
+
+    let batches = schedule.batches(Stage::PostUpdate).unwrap();
+    let sizes: Vec<usize> = batches.iter().map(Vec::len).collect();
+    assert_eq!(sizes, vec![1, 2, 1, 2]);
+    assert_eq!(batches[0], vec!["necst::tests::movement"]);
+    assert_eq!(batches[1][0], "necst::tests::friction");
+    assert_eq!(schedule.batches(Stage::Update).unwrap().len(), 1);
+
+    // The single threaded executor reaches the same state
+    let mut registries = [Registry::new(), Registry::new()];
+    for (registry, executor) in registries.iter_mut().zip([Executor::SingleThreaded, Executor::Parallel { threads: 4 }]) {
+        let mut schedule = Schedule::with_executor(executor);
+        schedule
+            .add_system(Stage::Startup, spawn)
+            .add_system(Stage::Update, friction.label("friction").after("movement"))
+            .add_system(Stage::Update, movement.label("movement"));
+        for _ in 0..3 {
+            schedule.run(registry).unwrap();
+        }
+    }
+    let [single, parallel] = &registries;
+    assert_eq!(single.view::<(&Position, &Velocity)>().map(|(_, item)| item).collect::<Vec<_>>(),
+               parallel.view::<(&Position, &Velocity)>().map(|(_, item)| item).collect::<Vec<_>>());
+
+    // The workers are kept from run to run
+    let threads = Arc::new(std::sync::Mutex::new(std::collections::HashSet::new()));
+    let mut schedule = Schedule::with_executor(Executor::Parallel { threads: 2 });
+    for _ in 0..2 {
+        let threads = threads.clone();
+        schedule.add_system(Stage::Update, move |_: View<(&Position,)>| {
+            threads.lock().unwrap().insert(std::thread::current().id());
+        });
+    }
+    for _ in 0..3 {
+        schedule.run(&mut registry).unwrap();
+    }
+    let threads = threads.lock().unwrap();
+    assert!(threads.len() <= 2 && !threads.contains(&std::thread::current().id()));
+}
+
+#[test]
+fn par_for_each() {
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    // Walking the sparse set of `Velocity`, so `Position` is looked up in its tables
+    let mut registry = Registry::new();
+    registry.register_storage::<Position>(StorageKind::Table);
+    for i in 0..1000 {
+        let entity = registry.create_with((Position { x: i, y: 0 },));
+        if i % 2 == 0 {
+            registry.add(entity, Velocity { dx: 1, dy: 2 });
+        }
+    }
+
+    registry.view_mut::<(&mut Position, &Velocity)>().par_for_each_batched(7, |_, (position, velocity)| {
+        position.x += velocity.dx;
+        position.y += velocity.dy;
+    });
+    let moved = registry.view::<(&Position, &Velocity)>().filter(|(_, (position, _))| position.y == 2).count();
+    assert_eq!(moved, 500);
+    assert_eq!(registry.view::<(&Position, Without<Velocity>)>().filter(|(_, (position, _))| position.y == 0).count(), 500);
+
+    let sum = AtomicI64::new(0);
+    registry.view_all::<(Position,)>().par_for_each(|_, (position,)| {
+        sum.fetch_add(position.x as i64, Ordering::Relaxed);
+    });
+    assert_eq!(sum.load(Ordering::Relaxed), (0..1000).sum::<i64>() + 500);
+
+    let last_run = registry.change_tick();
+    registry.advance_tick();
+    registry.view_mut::<(&mut Velocity,)>().par_for_each(|_, (velocity,)| velocity.dx = 0);
+    assert_eq!(registry.view_all::<(Velocity,)>().iter().filter(|(_, (velocity,))| velocity.dx == 0).count(), 500);
+    assert!(registry.ticks::<Velocity>(registry.view::<(&Velocity,)>().next().unwrap().0).unwrap().is_changed(last_run));
+}
+
+#[test]
+fn resources() {
+    #[derive(Debug, PartialEq)]
+    struct Time(u32);
+
+    let mut registry = Registry::new();
+    assert_eq!(registry.resource::<Time>(), None);
+    assert_eq!(registry.insert_resource(Time(1)), None);
+    assert!(registry.contains_resource::<Time>());
+    assert_eq!(registry.resource::<Time>(), Some(&Time(1)));
+    assert_eq!(registry.resource_ticks::<Time>(), Some(ComponentTicks::new(1)));
+
+    registry.advance_tick();
+    assert!(!registry.resource_mut::<Time>().unwrap().is_changed());
+    registry.resource_mut::<Time>().unwrap().0 += 1;
+    assert_eq!(registry.resource_ticks::<Time>(), Some(ComponentTicks { added: 1, changed: 2 }));
+    assert_eq!(registry.insert_resource(Time(5)), Some(Time(2)));
+    assert_eq!(registry.remove_resource::<Time>(), Some(Time(5)));
+    assert_eq!(registry.remove_resource::<Time>(), None);
+
+    // Systems see resource changes made since they last ran, like component changes
+    fn tick(mut time: ResMut<Time>, view: View<(&mut Position,)>) {
+        time.0 += 1;
+        for (_, (position,)) in view {
+            position.x = time.0 as i32;
+        }
+    }
+    use std::sync::{Arc, Mutex};
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    let mut schedule = Schedule::new();
+    schedule
+        .add_system(Stage::PreUpdate, tick)
+        .add_system(Stage::Update, move |time: Res<Time>| log.lock().unwrap().push((time.0, time.is_changed())))
+        .add_system(Stage::Update, |_: Res<Time>| {});
+    registry.insert_resource(Time(0));
+    registry.create_with((Position::default(),));
+    schedule.run(&mut registry).unwrap();
+    schedule.run(&mut registry).unwrap();
+    assert_eq!(*seen.lock().unwrap(), vec![(1, true), (2, true)]);
+    assert_eq!(registry.view::<(&Position,)>().next().unwrap().1, (&Position { x: 2, y: 0 },));
+    assert_eq!(schedule.batches(Stage::Update).unwrap().len(), 1);
+
+    let mut access = Access::default();
+    <(Res<Time>, ResMut<Time>) as SystemParam>::access(&mut access);
+    assert_eq!(access.conflicts().len(), 1);
+    let (mut read, mut write) = (Access::default(), Access::default());
+    read.read_resource::<Time>();
+    write.write_resource::<Time>();
+    assert!(!read.is_compatible(&write) && read.is_compatible(&read));
+}
+
+#[test]
+fn commands() {
+    use std::sync::Mutex;
+
+    let mut registry = Registry::new();
+    let entities: Vec<Entity> = (0..4).map(|i| registry.create_with((Position { x: i, y: i },))).collect();
+    let reserved = registry.reserve();
+    assert!(!registry.exists(reserved));
+
+    let mut commands = Commands::new();
+    for (entity, (position,)) in registry.view_all::<(Position,)>() {
+        if position.x % 2 == 0 {
+            commands.destroy(entity);
+        } else {
+            commands.add(entity, Velocity { dx: 1, dy: 0 }).remove::<Position>(entity);
+        }
+    }
+    commands.create_reserved_with(reserved, (Position::default(), Frozen)).add(reserved, Color::default());
+    commands.create_with((Velocity::default(),)).add(entities[0], Frozen);
+    assert_eq!(commands.len(), 11);
+    registry.apply(commands);
+
+    assert!(!registry.exists(entities[0]) && !registry.exists(entities[2]));
+    assert_eq!(registry.get_all::<(Position, Velocity)>(entities[1]), (None, Some(&Velocity { dx: 1, dy: 0 })));
+    assert_eq!(registry.get_all::<(Position, Frozen, Color)>(reserved), (Some(&Position::default()), Some(&Frozen), Some(&Color::default())));
+    assert_eq!(registry.view::<(&Velocity,)>().count(), 3);
+    assert_eq!(registry.create_reserved(reserved), Err(NecsError::NotReserved(reserved)));
+    assert_eq!(registry.create_reserved(entities[0]), Err(NecsError::NotReserved(entities[0])));
+
+    // Buffers filled concurrently merge in the order they are collected, not the order they were filled in
+    let buffers = Mutex::new(Vec::new());
+    registry.view_all::<(Velocity,)>().par_for_each_batched(1, |entity, _| {
+        let mut commands = Commands::new();
+        commands.emplace_or_replace(entity, Position { x: 1, y: 1 }).emplace_or_replace(entity, Position { x: 2, y: 2 });
+        buffers.lock().unwrap().push((entity, commands));
+    });
+    let mut buffers = buffers.into_inner().unwrap();
+    buffers.sort_by_key(|(entity, _)| *entity);
+    registry.apply(buffers.into_iter().map(|(_, commands)| commands).collect());
+    assert_eq!(registry.view::<(&Position, &Velocity)>().filter(|(_, (position, _))| position.x == 2).count(), 3);
+}
+
+#[test]
+fn reserve() {
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    let mut registry = Registry::new();
+    let entities: Vec<Entity> = (0..10).map(|_| registry.create()).collect();
+    for entity in &entities[..5] {
+        registry.destroy(*entity);
+    }
+
+    // Freed indices are handed out first, then fresh ones, without any id handed out twice
+    let reserved = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                let entities: Vec<Entity> = (0..25).map(|_| registry.reserve()).collect();
+                reserved.lock().unwrap().extend(entities);
+            });
+        }
+    });
+    let reserved = reserved.into_inner().unwrap();
+    assert_eq!(reserved.iter().collect::<HashSet<_>>().len(), 100);
+    assert_eq!(reserved.iter().filter(|entity| entity.generation() == 1).count(), 5);
+    assert_eq!(reserved.iter().map(|entity| entity.index()).max(), Some(104));
+    assert!(reserved.iter().all(|entity| !registry.exists(*entity)));
+
+    // Reserved ids can be referred to by commands recorded before the entity exists
+    let mut commands = Commands::new();
+    for (i, entity) in reserved.iter().enumerate() {
+        commands.create_reserved_with(*entity, (Position { x: i as i32, y: 0 },));
+    }
+    let child = registry.reserve();
+    commands.create_reserved(child).add(child, Velocity::default());
+    registry.apply(commands);
+    assert_eq!(registry.view::<(&Position,)>().count(), 100);
+    assert!(registry.has_id(child, std::any::TypeId::of::<Velocity>()));
+
+    let created = registry.create();
+    assert!(!reserved.contains(&created) && created != child && created.index() == 106);
+    assert_eq!(registry.create_reserved(registry.reserve()), Ok(()));
+
+    // Ids reserved but never created are handed back by destroying them
+    let abandoned = registry.reserve();
+    registry.destroy(created);
+    registry.destroy(abandoned);
+    registry.destroy(abandoned);
+    let recycled = [registry.create(), registry.create()];
+    assert_eq!(recycled.map(|entity| entity.index()), [abandoned.index(), created.index()]);
+    assert!(recycled.iter().all(|entity| entity.generation() == 1));
+}
+
+#[test]
+fn archetype_storage() {
+    use std::any::TypeId;
+
+    let mut registry = Registry::with_default_storage(StorageKind::Table);
+    let a = registry.create_with((Position { x: 1, y: 1 }, Velocity { dx: 1, dy: 0 }));
+    let b = registry.create_with((Position { x: 2, y: 2 },));
+    let c = registry.create_with((Position { x: 3, y: 3 }, Velocity { dx: 0, dy: 1 }, Color::default()));
+    let d = registry.create_with((Position { x: 4, y: 4 }, Velocity { dx: 2, dy: 2 }));
+    assert_eq!(registry.storage_kind::<Position>(), StorageKind::Table);
+
+    // {}, {P}, {P, V}, {P, V, C}
+    let tables: Vec<usize> = registry.archetypes().map(Archetype::len).collect();
+    assert_eq!(tables, vec![0, 1, 2, 1]);
+    let both = registry.archetypes().find(|archetype| archetype.len() == 2).unwrap();
+    assert_eq!(both.entities(), &[a, d]);
+    assert!(both.contains(TypeId::of::<Velocity>()) && !both.contains(TypeId::of::<Color>()));
+
+    assert_eq!(registry.get::<Position>(c), Some(&Position { x: 3, y: 3 }));
+    assert_eq!(registry.try_get::<Velocity>(b), Err(NecsError::ComponentMissing { entity: b, type_name: std::any::type_name::<Velocity>() }));
+    for (_, (position, velocity)) in registry.view_mut::<(&mut Position, &Velocity)>() {
+        position.x += velocity.dx;
+        position.y += velocity.dy;
+    }
+    let moved: Vec<_> = registry.view::<(&Position, Without<Color>)>().map(|(entity, (position, _))| (entity, position.x)).collect();
+    assert_eq!(moved, vec![(b, 2), (a, 2), (d, 6)]);
+    assert_eq!(registry.view::<(&Position, With<Color>)>().next().unwrap().1, (&Position { x: 3, y: 4 }, ()));
+    assert_eq!(registry.view::<(&Color, Option<&Velocity>)>().len(), 1);
+
+    // Removing moves the entity along the graph, to tables that already exist
+    registry.remove::<Velocity>(a);
+    registry.remove::<Color>(c);
+    let tables: Vec<usize> = registry.archetypes().map(Archetype::len).collect();
+    assert_eq!(tables, vec![0, 2, 2, 0]);
+    assert_eq!(registry.get_all::<(Position, Velocity)>(c), (Some(&Position { x: 3, y: 4 }), Some(&Velocity { dx: 0, dy: 1 })));
+    registry.add(a, Velocity::default());
+    registry.add(c, Color::default());
+    assert_eq!(registry.archetypes().count(), 4);
+
+    // Change ticks follow components from table to table
+    registry.advance_tick();
+    let last_run = registry.last_change_tick();
+    registry.patch::<Velocity>(d).with(|velocity| velocity.dx = 0);
+    registry.add(b, Velocity::default());
+    let changed: Vec<Entity> = registry.view_mut::<(Changed<Velocity>,)>().map(|(entity, _)| entity).collect();
+    assert_eq!(changed.len(), 2);
+    assert!(changed.contains(&d) && changed.contains(&b));
+    assert_eq!(registry.view::<(Added<Velocity>,)>().count(), 1);
+    assert!(!registry.ticks::<Position>(b).unwrap().is_changed(last_run));
+
+    registry.view_mut::<(&mut Position,)>().par_for_each_batched(1, |_, (position,)| position.x = 0);
+    assert!(registry.view::<(&Position,)>().all(|(_, (position,))| position.x == 0));
+
+    registry.destroy(c);
+    registry.clear::<Position>();
+    assert_eq!(registry.view::<(&Velocity,)>().count(), 3);
+    assert_eq!(registry.view::<(&Position,)>().count(), 0);
+    assert!(registry.archetypes().all(|archetype| archetype.is_empty() || archetype.components() == [TypeId::of::<Velocity>()]));
+}
+
+#[test]
+fn storage_selection() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+    #[derive(Debug, PartialEq)]
+    struct Name(&'static str);
+    #[derive(Debug, PartialEq)]
+    struct Player;
+    impl Drop for Player {
+        fn drop(&mut self) { DROPS.fetch_add(1, Ordering::Relaxed); }
+    }
+
+    let mut registry = Registry::with_default_storage(StorageKind::Table);
+    registry.register_storage::<Name>(StorageKind::SparseSet);
+    assert_eq!(registry.storage_kind::<Position>(), StorageKind::Table);
+    assert_eq!(registry.storage_kind::<Health>(), StorageKind::Table);
+    assert_eq!(registry.storage_kind::<Name>(), StorageKind::SparseSet);
+    assert_eq!(registry.storage_kind::<Player>(), StorageKind::Tag);
+
+    let first = registry.create_with((Health(10), Name("first"), Player));
+    let second = registry.create_with((Health(20), Name("second")));
+    let third = registry.create_with((Name("third"), Player));
+    assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+
+    assert_eq!(registry.get::<Health>(first), Some(&Health(10)));
+    assert!(registry.get::<Player>(first).is_some());
+    assert_eq!(registry.get::<Player>(second), None);
+    assert!(registry.ticks::<Player>(third).is_some());
+    assert_eq!(registry.archetypes().filter(|archetype| !archetype.is_empty()).count(), 1);
+
+    let mut players: Vec<_> = registry.view_all::<(Name, Player)>().map(|(entity, (name, _))| (entity, name.0)).collect();
+    players.sort();
+    assert_eq!(players, [(first, "first"), (third, "third")]);
+    for (_, (health, _)) in registry.view_mut::<(&mut Health, &Player)>() {
+        health.0 += 1;
+    }
+    assert_eq!(registry.get::<Health>(first), Some(&Health(11)));
+    assert_eq!(registry.get::<Health>(second), Some(&Health(20)));
+    assert_eq!(registry.view::<(&Health, Without<Player>)>().map(|(entity, _)| entity).collect::<Vec<_>>(), [second]);
 
-    assert!(false);
+    registry.remove::<Player>(first);
+    assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+    registry.destroy(third);
+    assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+    assert_eq!(registry.view_all::<(Player,)>().count(), 0);
+
+    // Foreign types are components too, and zero-sized types may opt out of tags
+    let mut registry = Registry::new();
+    registry.register_storage::<String>(StorageKind::Table).register_storage::<Frozen>(StorageKind::SparseSet);
+    let entity = registry.create_with((String::from("name"), Frozen));
+    assert_eq!(registry.get::<String>(entity).map(String::as_str), Some("name"));
+    assert_eq!(registry.storage_kind::<Frozen>(), StorageKind::SparseSet);
+    assert_eq!(registry.archetypes().filter(|archetype| !archetype.is_empty()).count(), 1);
+    assert_eq!(registry.view_all::<(String, Frozen)>().count(), 1);
+}
+
+#[test]
+#[should_panic(expected = "already has a storage")]
+fn storage_registered_late() {
+    let mut registry = Registry::new();
+    registry.create_with((Position::default(),));
+    registry.register_storage::<Position>(StorageKind::Table);
+}
+
+#[test]
+fn tags() {
+    let mut registry = Registry::with_default_storage(StorageKind::Table);
+    assert_eq!(registry.storage_kind::<Frozen>(), StorageKind::Tag);
+    assert_eq!(registry.storage_kind::<Position>(), StorageKind::Table);
+
+    let frozen = registry.create_with((Position { x: 1, y: 1 },));
+    let moving = registry.create_with((Position { x: 2, y: 2 },));
+    registry.tag::<Frozen>(frozen);
+    registry.tag::<Frozen>(frozen);
+    assert!(registry.has::<Frozen>(frozen));
+    assert!(!registry.has::<Frozen>(moving));
+    assert!(registry.has::<Position>(moving));
+    assert_eq!(registry.view_all::<(Frozen,)>().count(), 1);
+
+    for (_, position) in registry.view_mut::<(&mut Position, Without<Frozen>)>() {
+        position.0.x += 10;
+    }
+    assert_eq!(registry.get::<Position>(frozen), Some(&Position { x: 1, y: 1 }));
+    assert_eq!(registry.get::<Position>(moving), Some(&Position { x: 12, y: 2 }));
+    assert_eq!(registry.view::<(&Position, &Frozen)>().map(|(entity, (position, _))| (entity, position.x)).collect::<Vec<_>>(), [(frozen, 1)]);
+    assert_eq!(registry.view::<(With<Frozen>,)>().count(), 1);
+
+    registry.remove::<Frozen>(frozen);
+    assert!(!registry.has::<Frozen>(frozen));
+    assert_eq!(registry.view::<(&Position, With<Frozen>)>().count(), 0);
+
+    // Tags are dropped when removed, or along with the registry
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+    struct Counted;
+    impl Drop for Counted {
+        fn drop(&mut self) { DROPS.fetch_add(1, Ordering::SeqCst); }
+    }
+
+    let mut registry = Registry::new();
+    let entities: Vec<Entity> = (0..3).map(|_| registry.create_with((Counted,))).collect();
+    registry.remove::<Counted>(entities[0]);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+    drop(registry);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn owning_group() {
+    fn assert_packed(registry: &mut Registry) {
+        let entities = registry.group::<(Position, Velocity), (), (Frozen,)>().entities().to_vec();
+        assert_eq!(&registry.storage::<Position>().unwrap().entities()[..entities.len()], &entities[..]);
+        assert_eq!(&registry.storage::<Velocity>().unwrap().entities()[..entities.len()], &entities[..]);
+    }
+
+    let mut registry = Registry::new();
+    let position = registry.create_with((Position { x: 1, y: 1 },));
+    let a = registry.create_with((Position { x: 0, y: 0 }, Velocity { dx: 1, dy: 0 }));
+    let velocity = registry.create_with((Velocity { dx: 5, dy: 5 },));
+    let frozen = registry.create_with((Position::default(), Velocity { dx: 9, dy: 9 }, Frozen));
+    let b = registry.create_with((Velocity { dx: 0, dy: 2 }, Position { x: 3, y: 3 }));
+
+    let mut group = registry.group::<(Position, Velocity), (), (Frozen,)>();
+    assert_eq!(group.len(), 2);
+    assert!(group.contains(a) && group.contains(b) && !group.contains(frozen) && !group.contains(position));
+    for (_, (position, velocity)) in group.view_mut::<(&mut Position, &Velocity)>() {
+        position.x += velocity.dx;
+        position.y += velocity.dy;
+    }
+    assert_eq!(registry.get::<Position>(a), Some(&Position { x: 1, y: 0 }));
+    assert_eq!(registry.get::<Position>(b), Some(&Position { x: 3, y: 5 }));
+    assert_eq!(registry.get::<Position>(frozen), Some(&Position::default()));
+    assert_packed(&mut registry);
+
+    registry.add(position, Velocity::default());
+    registry.remove::<Frozen>(frozen);
+    registry.add(b, Frozen);
+    registry.remove::<Position>(velocity);
+    assert_packed(&mut registry);
+    let group = registry.group::<(Position, Velocity), (), (Frozen,)>();
+    assert_eq!(group.len(), 3);
+    assert!(group.contains(position) && group.contains(frozen) && !group.contains(b));
+
+    registry.remove::<Velocity>(a);
+    registry.destroy(frozen);
+    let c = registry.create_with((Position::default(), Velocity::default()));
+    assert_packed(&mut registry);
+    let group = registry.group::<(Position, Velocity), (), (Frozen,)>();
+    assert_eq!(group.view::<(&Position,)>().map(|(entity, _)| entity).collect::<Vec<_>>(), [position, c]);
+
+    registry.clear::<Velocity>();
+    assert!(registry.group::<(Position, Velocity), (), (Frozen,)>().is_empty());
+    registry.add(a, Velocity::default());
+    assert_eq!(registry.group::<(Position, Velocity), (), (Frozen,)>().entities(), [a]);
+}
+
+#[test]
+#[should_panic(expected = "already owned by another group")]
+fn owning_group_conflict() {
+    let mut registry = Registry::new();
+    registry.group::<(Position, Velocity), (), ()>();
+    registry.group::<(Position,), (Color,), ()>();
+}
+
+#[test]
+fn non_owning_group() {
+    let mut registry = Registry::new();
+    for x in 0..10 {
+        registry.create_with((Position { x, y: 0 }, Velocity::default()));
+        registry.create_with((Position { x, y: 0 }, Color::default()));
+        registry.create_with((Velocity::default(), Color::default()));
+    }
+    let a = registry.create_with((Position::default(), Velocity::default(), Color::default()));
+    let b = registry.create_with((Velocity::default(), Color::default()));
+    assert_eq!(registry.view_all::<(Position, Velocity, Color)>().len(), 21);
+
+    let group = registry.group::<(), (Position, Velocity, Color), ()>();
+    assert_eq!(group.entities(), [a]);
+    // The cached set is walked instead of the smallest storage
+    assert_eq!(registry.view_all::<(Color, Velocity, Position)>().len(), 1);
+    assert_eq!(registry.view_all::<(Position, Velocity)>().len(), 21);
+
+    registry.add(b, Position::default());
+    let c = registry.create_with((Color::default(), Position::default(), Velocity::default()));
+    registry.remove::<Color>(a);
+    assert_eq!(registry.view_all::<(Position, Velocity, Color)>().map(|(entity, _)| entity).collect::<Vec<_>>(), [c, b]);
+    registry.destroy(b);
+    assert_eq!(registry.view_all::<(Position, Velocity, Color)>().len(), 1);
+
+    registry.group::<(Velocity,), (), (Frozen,)>();
+    registry.tag::<Frozen>(a);
+    let groups: Vec<GroupInfo> = registry.groups().collect();
+    assert_eq!(groups, [
+        GroupInfo { owned: vec![], get: vec![type_name::<Position>(), type_name::<Velocity>(), type_name::<Color>()], exclude: vec![], len: 1 },
+        GroupInfo { owned: vec![type_name::<Velocity>()], get: vec![], exclude: vec![type_name::<Frozen>()], len: 21 },
+    ]);
+}
+
+#[test]
+fn sort() {
+    let mut registry = Registry::new();
+    let entities: Vec<Entity> = [3, 1, 4, 1, 5, 9, 2, 6].iter().map(|x| registry.create_with((Position { x: *x, y: 0 },))).collect();
+    for entity in entities.iter().step_by(2) {
+        registry.add(*entity, Velocity::default());
+    }
+
+    registry.sort::<Position>(|a, b| b.x.cmp(&a.x));
+    let xs: Vec<i32> = registry.view::<(&Position,)>().map(|(_, (position,))| position.x).collect();
+    assert_eq!(xs, [9, 6, 5, 4, 3, 2, 1, 1]);
+
+    registry.sort_as::<Position, Velocity>();
+    let followed: Vec<i32> = registry.view::<(&Velocity, &Position)>().map(|(_, (_, position))| position.x).collect();
+    assert_eq!(followed, [5, 4, 3, 2]);
+
+    // Sorting an owned storage keeps the group packed
+    registry.group::<(Position, Velocity), (), ()>();
+    registry.sort::<Position>(|a, b| a.x.cmp(&b.x));
+    let group = registry.group::<(Position, Velocity), (), ()>();
+    assert_eq!(group.view::<(&Position,)>().map(|(_, (position,))| position.x).collect::<Vec<_>>(), [2, 3, 4, 5]);
+    assert_eq!(registry.storage::<Velocity>().unwrap().entities(), &registry.storage::<Position>().unwrap().entities()[..4]);
+    let xs: Vec<i32> = registry.storage::<Position>().unwrap().values().iter().map(|position| position.x).collect();
+    assert_eq!(xs, [2, 3, 4, 5, 1, 1, 6, 9]);
+    assert!(registry.ticks::<Position>(entities[5]).is_some_and(|ticks| ticks.added == ticks.changed));
+
+    // Owned components no entity has yet have no storage to reorder
+    let mut registry = Registry::new();
+    registry.create_with((Position { x: 2, y: 0 },));
+    registry.create_with((Position { x: 1, y: 0 },));
+    registry.group::<(Position, Color), (), ()>();
+    registry.sort::<Position>(|a, b| a.x.cmp(&b.x));
+    assert_eq!(registry.view::<(&Position,)>().map(|(_, (position,))| position.x).collect::<Vec<_>>(), [1, 2]);
+
+    // Groups caching a view follow the storage of the first component they get
+    let mut registry = Registry::new();
+    for x in [3, 1, 2] {
+        registry.create_with((Position { x, y: 0 }, Velocity::default()));
+    }
+    registry.group::<(), (Position, Velocity), ()>();
+    registry.sort::<Position>(|a, b| a.x.cmp(&b.x));
+    let cached: Vec<i32> = registry.view_all::<(Position, Velocity)>().map(|(_, (position, _))| position.x).collect();
+    assert_eq!(cached, [1, 2, 3]);
+    assert_eq!(registry.view::<(&Position, &Velocity)>().map(|(_, (position, _))| position.x).collect::<Vec<_>>(), cached);
+}
+
+#[test]
+fn iteration_order() {
+    fn run(registry: &mut Registry) -> (Vec<i32>, Vec<u32>) {
+        let entities: Vec<Entity> = (0..8).map(|x| registry.create_with((Position { x, y: 0 }, Velocity::default()))).collect();
+        registry.remove::<Position>(entities[1]);
+        registry.destroy(entities[4]);
+        registry.remove::<Position>(entities[0]);
+        registry.add(entities[0], Position { x: 0, y: 1 });
+        let positions = registry.view::<(&Position,)>().map(|(_, (position,))| position.x).collect();
+        let moving = registry.view::<(&Velocity,)>().map(|(entity, _)| entity.index()).collect();
+        (positions, moving)
+    }
+
+    let mut registry = Registry::with_iteration_order(IterationOrder::Insertion);
+    assert_eq!(run(&mut registry), (vec![2, 3, 5, 6, 7, 0], vec![0, 1, 2, 3, 5, 6, 7]));
+    registry = Registry::with_default_storage(StorageKind::Table);
+    registry.set_iteration_order(IterationOrder::Insertion);
+    // Tables keep their rows in order, and are walked in the order they were created
+    assert_eq!(run(&mut registry), (vec![2, 3, 5, 6, 7, 0], vec![2, 3, 5, 6, 7, 0, 1]));
+
+    // Packed storages fill holes with their last entry, which is just as reproducible
+    assert_eq!(run(&mut Registry::new()), (vec![5, 7, 2, 3, 6, 0], vec![0, 1, 2, 3, 7, 5, 6]));
+}
+
+#[test]
+fn hierarchy() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let mut registry = Registry::new();
+    let updates = Arc::new(AtomicUsize::new(0));
+    let counter = updates.clone();
+    registry.on_update::<Children>().connect(move |_, _| { counter.fetch_add(1, Ordering::SeqCst); });
+    let [root, a, b, c, d, e]: [Entity; 6] = std::array::from_fn(|_| registry.create());
+    registry.set_parent(a, root);
+    registry.set_parent(b, root);
+    registry.set_parent(c, a);
+    registry.set_parent(d, a);
+    registry.set_parent(e, b);
+    assert_eq!(updates.load(Ordering::SeqCst), 2);
+
+    assert_eq!(registry.parent(c), Some(a));
+    assert_eq!(registry.parent(root), None);
+    assert_eq!(registry.children(root).collect::<Vec<_>>(), [a, b]);
+    assert_eq!(registry.descendants_depth_first(root).collect::<Vec<_>>(), [a, c, d, b, e]);
+    assert_eq!(registry.descendants_breadth_first(root).collect::<Vec<_>>(), [a, b, c, d, e]);
+    assert_eq!(registry.view::<(&Parent, With<Children>)>().count(), 2);
+
+    // Moving a subtree detaches it from its previous parent
+    registry.set_parent(b, c);
+    assert_eq!(registry.children(root).collect::<Vec<_>>(), [a]);
+    assert_eq!(registry.descendants_depth_first(root).collect::<Vec<_>>(), [a, c, b, e, d]);
+
+    // Destroying either side keeps the other consistent
+    registry.destroy(d);
+    assert_eq!(registry.children(a).collect::<Vec<_>>(), [c]);
+    assert_eq!(updates.load(Ordering::SeqCst), 4);
+    registry.destroy(c);
+    assert_eq!(registry.parent(b), None);
+    assert!(registry.get::<Children>(a).is_none());
+    registry.remove_parent(e);
+    assert!(registry.get::<Children>(b).is_none());
+
+    let mut commands = Commands::new();
+    commands.set_parent(e, a).set_parent(b, e);
+    registry.apply(commands);
+    registry.add(b, Position::default());
+    registry.destroy_recursive(a);
+    assert!(!registry.exists(a) && !registry.exists(e) && !registry.exists(b));
+    assert!(registry.exists(root) && registry.children(root).next().is_none());
+    assert!(registry.view::<(&Position,)>().next().is_none());
+
+    // Commands on entities destroyed meanwhile do nothing
+    let mut commands = Commands::new();
+    commands.set_parent(root, a);
+    registry.apply(commands);
+    assert_eq!(registry.parent(root), None);
+}
+
+#[test]
+#[should_panic(expected = "cannot be attached to itself or its descendant")]
+fn hierarchy_cycle() {
+    let mut registry = Registry::new();
+    let (parent, child) = (registry.create(), registry.create());
+    registry.set_parent(child, parent);
+    registry.set_parent(parent, child);
+}
+
+#[test]
+#[should_panic(expected = "is maintained by the registry")]
+fn hierarchy_write() {
+    let mut registry = Registry::new();
+    let (parent, child) = (registry.create(), registry.create());
+    registry.set_parent(child, parent);
+    registry.view_mut::<(&mut Parent,)>();
+}
+
+#[test]
+#[should_panic(expected = "is maintained by the registry")]
+fn hierarchy_patch() {
+    let mut registry = Registry::new();
+    let (parent, child) = (registry.create(), registry.create());
+    registry.set_parent(child, parent);
+    registry.patch::<Children>(parent);
 }
@@ -0,0 +1,18 @@
+/// Value of the registry-wide change counter, which advances every time a system run completes.
+pub type Tick = u64;
+
+/// When a component was added and last changed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentTicks {
+    pub added: Tick,
+    pub changed: Tick,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: Tick) -> Self {
+        Self { added: tick, changed: tick }
+    }
+
+    pub fn is_added(&self, last_run: Tick) -> bool { self.added > last_run }
+    pub fn is_changed(&self, last_run: Tick) -> bool { self.changed > last_run }
+}
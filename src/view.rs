@@ -0,0 +1,189 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::{Archetype, Candidates, Entity, Fetch, ReadOnlyFetch, Registry, Tick};
+
+/// Lazy iterator over the entities matching `F`, walking the smallest required storage and probing the rest, or
+/// every entity when `F` requires none.
+///
+/// When that storage is made of archetype tables, the view walks the rows of the tables that can match one after
+/// the other, fetching table-stored components by row.
+pub struct View<'r, F: Fetch> {
+    state: Option<F::State<'r>>,
+    candidates: Candidates<'r>,
+    /// Index of the next table to walk, when the candidates are archetypes.
+    next_table: usize,
+    /// The table being walked, whose rows are `entities`.
+    table: Option<&'r Archetype>,
+    entities: &'r [Entity],
+    cursor: usize,
+}
+
+impl<'r, F: ReadOnlyFetch> View<'r, F> {
+    pub(crate) fn new(registry: &'r Registry) -> Self {
+        // SAFETY: nothing is fetched mutably
+        unsafe { Self::new_unchecked(registry, registry.last_change_tick()) }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<F::Item<'r>> {
+        let state = self.state.as_ref()?;
+        // SAFETY: nothing is fetched mutably
+        if F::matches(state, entity, None) { Some(unsafe { F::fetch(state, entity, None) }) } else { None }
+    }
+
+    pub fn iter(&self) -> View<'r, F> {
+        View::start(self.state, self.candidates)
+    }
+}
+
+impl<'r, F: Fetch> View<'r, F> {
+    /// # Safety
+    /// The storages `F` writes must not be borrowed anywhere else for `'r`.
+    pub(crate) unsafe fn new_unchecked(registry: &'r Registry, last_run: Tick) -> Self {
+        let state = F::init(registry, last_run);
+        let candidates = match &state {
+            // With no term requiring anything, every entity is a candidate
+            Some(state) => F::candidates(state).unwrap_or_else(|| Candidates::Entities(registry.live.entities())),
+            None => Candidates::Entities(&[]),
+        };
+        Self::start(state, candidates)
+    }
+
+    /// Like `new_unchecked`, walking `candidates` rather than the smallest storage `F` requires.
+    ///
+    /// # Safety
+    /// As for `new_unchecked`.
+    pub(crate) unsafe fn with_candidates(registry: &'r Registry, last_run: Tick, candidates: Candidates<'r>) -> Self {
+        Self::start(F::init(registry, last_run), candidates)
+    }
+
+    fn start(state: Option<F::State<'r>>, candidates: Candidates<'r>) -> Self {
+        let entities = match candidates {
+            Candidates::Entities(entities) => entities,
+            Candidates::Archetypes(..) => &[],
+        };
+        Self { state, candidates, next_table: 0, table: None, entities, cursor: 0 }
+    }
+
+    /// The tables left to walk after the current one.
+    fn remaining_tables(&self) -> impl Iterator<Item = &'r Archetype> + '_ {
+        let tables = match self.candidates {
+            Candidates::Archetypes(archetypes, ids) => Some((archetypes, &ids[self.next_table..])),
+            Candidates::Entities(_) => None,
+        };
+        tables.into_iter().flat_map(|(archetypes, ids)| ids.iter().map(move |id| archetypes.get(*id)))
+    }
+
+    /// Moves to the next matching entity, returning it with its row in the walked storage.
+    fn advance(&mut self) -> Option<(Entity, Option<usize>)> {
+        let state = self.state.as_mut()?;
+        loop {
+            while let Some(entity) = self.entities.get(self.cursor).copied() {
+                let row = Some(self.cursor);
+                self.cursor += 1;
+                if F::matches(state, entity, row) {
+                    return Some((entity, row));
+                }
+            }
+            let Candidates::Archetypes(archetypes, ids) = self.candidates else { return None };
+            let table = archetypes.get(*ids.get(self.next_table)?);
+            self.next_table += 1;
+            if F::matches_archetype(state, table) {
+                F::set_archetype(state, table);
+                (self.table, self.entities, self.cursor) = (Some(table), table.entities(), 0);
+            }
+        }
+    }
+
+    /// Upper bound of the entities left to visit: the length of the storage being walked.
+    pub fn len(&self) -> usize {
+        self.entities.len() - self.cursor + self.remaining_tables().map(Archetype::len).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        // The fields are all `Copy`, so the walk can be resumed on a copy without fetching anything
+        View::<F> { ..*self }.advance().is_none()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.state.as_ref().is_some_and(|state| F::matches(state, entity, None))
+    }
+
+    /// Calls `func` for every remaining entity, split in batches across the available threads.
+    pub fn par_for_each(self, func: impl Fn(Entity, F::Item<'r>) + Sync) where F::State<'r>: Sync {
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        let batch_size = self.len().div_ceil(threads * 4).max(1);
+        self.par_for_each_batched(batch_size, func)
+    }
+
+    /// Calls `func` for every remaining entity, handing batches of `batch_size` entities of the walked storage
+    /// to a scoped pool of worker threads. Batches never span two archetype tables.
+    pub fn par_for_each_batched(self, batch_size: usize, func: impl Fn(Entity, F::Item<'r>) + Sync) where F::State<'r>: Sync {
+        assert!(batch_size > 0, "par_for_each_batched: batch size must not be zero");
+        let Some(state) = self.state else { return };
+        // Each batch is its table, if any, the row of its first entity and its entities
+        let mut batches: Vec<(Option<&'r Archetype>, usize, &'r [Entity])> = Vec::new();
+        let mut split = |table: Option<&'r Archetype>, first: usize, entities: &'r [Entity]| {
+            let chunks = entities.chunks(batch_size).enumerate();
+            batches.extend(chunks.map(|(index, chunk)| (table, first + index * batch_size, chunk)));
+        };
+        split(self.table, self.cursor, &self.entities[self.cursor..]);
+        for table in self.remaining_tables().filter(|table| F::matches_archetype(&state, table)) {
+            split(Some(table), 0, table.entities());
+        }
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get()).min(batches.len());
+        let (next, state, func) = (AtomicUsize::new(0), &state, &func);
+        let run = || while let Some((table, first, entities)) = batches.get(next.fetch_add(1, Ordering::Relaxed)) {
+            let mut state = *state;
+            if let Some(table) = table {
+                F::set_archetype(&mut state, table);
+            }
+            for (index, entity) in entities.iter().copied().enumerate() {
+                let row = Some(first + index);
+                if F::matches(&state, entity, row) {
+                    // SAFETY: batches are disjoint, so every entity is visited at most once, and the view holds the access `F` declared
+                    func(entity, unsafe { F::fetch(&state, entity, row) });
+                }
+            }
+        };
+        if threads <= 1 {
+            return run();
+        }
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(run);
+            }
+        });
+    }
+}
+
+impl<'r, F: Fetch> Iterator for View<'r, F> {
+    type Item = (Entity, F::Item<'r>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (entity, row) = self.advance()?;
+        let state = self.state.as_ref()?;
+        // SAFETY: every entity is visited at most once and the view holds the access `F` declared
+        Some((entity, unsafe { F::fetch(state, entity, row) }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.len()))
+    }
+}
+
+impl<'r, F: ReadOnlyFetch> IntoIterator for &View<'r, F> {
+    type Item = (Entity, F::Item<'r>);
+    type IntoIter = View<'r, F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'r, F: ReadOnlyFetch> fmt::Debug for View<'r, F> where F::Item<'r>: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}